@@ -0,0 +1,957 @@
+use muxr_core::input::{MouseButton, MouseEvent, MouseKind};
+use muxr_core::search::{Direction, Match, Query};
+use muxr_core::state::{Col, Row, State};
+
+use std::cmp;
+use std::env;
+
+use termion::event::Key;
+
+/// Which set of key bindings copy mode uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyTable {
+    Vi,
+    Emacs,
+}
+
+impl KeyTable {
+    /// Use vi bindings if `$VISUAL` or `$EDITOR` mentions vi, and emacs
+    /// bindings otherwise.
+    pub fn from_env() -> Self {
+        let editor = env::var("VISUAL")
+            .or_else(|_| env::var("EDITOR"))
+            .unwrap_or_default();
+
+        if editor.contains("vi") {
+            KeyTable::Vi
+        } else {
+            KeyTable::Emacs
+        }
+    }
+
+    fn command(self, key: Key) -> Option<Command> {
+        match self {
+            KeyTable::Vi => vi(key),
+            KeyTable::Emacs => emacs(key),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionKind {
+    /// Everything between two characters, in reading order.
+    Char,
+
+    /// Whole lines.
+    Line,
+
+    /// A rectangle of columns.
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    LineStart,
+    LineEnd,
+    Top,
+    Bottom,
+    ViewTop,
+    ViewMiddle,
+    ViewBottom,
+    HalfPageUp,
+    HalfPageDown,
+    PageUp,
+    PageDown,
+    Select(SelectionKind),
+    ClearSelection,
+    Search(Direction),
+    SearchAgain,
+    SearchReverse,
+    Yank,
+    Cancel,
+}
+
+fn vi(key: Key) -> Option<Command> {
+    let command = match key {
+        Key::Char('h') | Key::Left | Key::Backspace => Command::Left,
+        Key::Char('l') | Key::Right => Command::Right,
+        Key::Char('k') | Key::Up => Command::Up,
+        Key::Char('j') | Key::Down => Command::Down,
+        Key::Char('w') => Command::WordForward,
+        Key::Char('b') => Command::WordBackward,
+        Key::Char('0') | Key::Home => Command::LineStart,
+        Key::Char('$') | Key::End => Command::LineEnd,
+        Key::Char('g') => Command::Top,
+        Key::Char('G') => Command::Bottom,
+        Key::Char('H') => Command::ViewTop,
+        Key::Char('M') => Command::ViewMiddle,
+        Key::Char('L') => Command::ViewBottom,
+        Key::Ctrl('u') => Command::HalfPageUp,
+        Key::Ctrl('d') => Command::HalfPageDown,
+        Key::Ctrl('b') | Key::PageUp => Command::PageUp,
+        Key::Ctrl('f') | Key::PageDown => Command::PageDown,
+        Key::Char('v') | Key::Char(' ') => Command::Select(SelectionKind::Char),
+        Key::Char('V') => Command::Select(SelectionKind::Line),
+        Key::Ctrl('v') => Command::Select(SelectionKind::Block),
+        Key::Esc => Command::ClearSelection,
+        Key::Char('/') => Command::Search(Direction::Forward),
+        Key::Char('?') => Command::Search(Direction::Backward),
+        Key::Char('n') => Command::SearchAgain,
+        Key::Char('N') => Command::SearchReverse,
+        Key::Char('y') | Key::Char('\n') => Command::Yank,
+        Key::Char('q') => Command::Cancel,
+        _ => return None,
+    };
+
+    Some(command)
+}
+
+fn emacs(key: Key) -> Option<Command> {
+    let command = match key {
+        Key::Ctrl('b') | Key::Left => Command::Left,
+        Key::Ctrl('f') | Key::Right => Command::Right,
+        Key::Ctrl('p') | Key::Up => Command::Up,
+        Key::Ctrl('n') | Key::Down => Command::Down,
+        Key::Alt('f') => Command::WordForward,
+        Key::Alt('b') => Command::WordBackward,
+        Key::Ctrl('a') | Key::Home => Command::LineStart,
+        Key::Ctrl('e') | Key::End => Command::LineEnd,
+        Key::Alt('<') => Command::Top,
+        Key::Alt('>') => Command::Bottom,
+        Key::Alt('r') => Command::ViewMiddle,
+        Key::Alt('v') | Key::PageUp => Command::PageUp,
+        Key::Ctrl('v') | Key::PageDown => Command::PageDown,
+        // Ctrl-Space arrives as a NUL.
+        Key::Null => Command::Select(SelectionKind::Char),
+        Key::Char('L') => Command::Select(SelectionKind::Line),
+        Key::Char('R') => Command::Select(SelectionKind::Block),
+        Key::Ctrl('g') => Command::ClearSelection,
+        Key::Ctrl('s') => Command::Search(Direction::Forward),
+        Key::Ctrl('r') => Command::Search(Direction::Backward),
+        Key::Char('n') => Command::SearchAgain,
+        Key::Char('N') => Command::SearchReverse,
+        Key::Alt('w') | Key::Char('\n') => Command::Yank,
+        Key::Esc | Key::Char('q') => Command::Cancel,
+        _ => return None,
+    };
+
+    Some(command)
+}
+
+/// How many lines each step of the mouse wheel scrolls.
+const WHEEL_LINES: u64 = 3;
+
+/// Whether `event` should start copy mode when muxr has the mouse: rolling
+/// the wheel up into the scrollback, or dragging with the left button to
+/// select.
+pub fn starts_with(event: &MouseEvent) -> bool {
+    matches!(
+        (event.kind, event.button),
+        (MouseKind::Press, Some(MouseButton::WheelUp)) | (MouseKind::Drag, Some(MouseButton::Left))
+    )
+}
+
+/// What the client should do after a key is handled in copy mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Continue,
+    Exit,
+    Yank(String),
+}
+
+/// A line number, as used by `State::line`, and a column.
+pub type Position = (u64, u16);
+
+/// A search query being typed.
+#[derive(Debug, Clone)]
+struct Prompt {
+    direction: Direction,
+    text: String,
+    regex: bool,
+
+    /// Where the cursor was before the search, to go back to if it's
+    /// cancelled.
+    origin: Position,
+}
+
+/// The last search, which `n` and `N` repeat.
+#[derive(Debug, Clone)]
+struct Search {
+    query: Query,
+    text: String,
+    direction: Direction,
+}
+
+/// Moves a cursor around a snapshot of the screen and scrollback, and
+/// selects text to copy from it.
+#[derive(Debug)]
+pub struct CopyMode {
+    state: State,
+    keys: KeyTable,
+    height: u16,
+    top: u64,
+    cursor: Position,
+    selection: Option<(SelectionKind, Position)>,
+
+    /// Whether the selection is being made by dragging the mouse.
+    dragging: bool,
+
+    prompt: Option<Prompt>,
+    search: Option<Search>,
+    message: Option<String>,
+}
+
+impl CopyMode {
+    /// Start copy mode on `state`, which should include the scrollback, with
+    /// the cursor where the terminal's cursor is and `height` rows visible.
+    pub fn new(state: State, keys: KeyTable, height: Row) -> Self {
+        let top = state.screen_start();
+        let (row, col) = state.cursor.position;
+
+        let mut copy = CopyMode {
+            cursor: (top + u64::from(row.0), col.0),
+            state,
+            keys,
+            height: 1,
+            top,
+            selection: None,
+            dragging: false,
+            prompt: None,
+            search: None,
+            message: None,
+        };
+
+        copy.set_height(height);
+        copy
+    }
+
+    /// The snapshot being copied from.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// The line shown on the first row.
+    pub fn top(&self) -> u64 {
+        self.top
+    }
+
+    pub fn cursor(&self) -> Position {
+        self.cursor
+    }
+
+    /// Change how many rows are visible.
+    pub fn set_height(&mut self, height: Row) {
+        self.height = cmp::max(height.0, 1);
+        self.scroll_to_cursor();
+    }
+
+    /// Whether the cell at `col` on `line` is selected.
+    pub fn is_selected(&self, line: u64, col: u16) -> bool {
+        let (kind, anchor) = match self.selection {
+            Some(s) => s,
+            None => return false,
+        };
+
+        let (start, end) = ordered(anchor, self.cursor);
+
+        match kind {
+            SelectionKind::Char => start <= (line, col) && (line, col) <= end,
+            SelectionKind::Line => start.0 <= line && line <= end.0,
+            SelectionKind::Block => {
+                let left = cmp::min(anchor.1, self.cursor.1);
+                let right = cmp::max(anchor.1, self.cursor.1);
+
+                start.0 <= line && line <= end.0 && left <= col && col <= right
+            }
+        }
+    }
+
+    /// The search prompt while a query is being typed, or a message about
+    /// the last search.
+    pub fn status(&self) -> Option<String> {
+        match &self.prompt {
+            Some(prompt) => {
+                let mode = if prompt.regex { "regex " } else { "" };
+                let arrow = match prompt.direction {
+                    Direction::Forward => "/",
+                    Direction::Backward => "?",
+                };
+
+                Some(format!("{}{}{}", mode, arrow, prompt.text))
+            }
+            None => self.message.clone(),
+        }
+    }
+
+    /// Matches of the current search on the visible lines, to highlight.
+    pub fn matches(&self) -> Vec<Match> {
+        let lines = self.top..self.top + u64::from(self.height);
+
+        match (&self.prompt, &self.search) {
+            (Some(prompt), _) => prompt_query(prompt)
+                .map(|q| self.state.matches(&q, lines))
+                .unwrap_or_default(),
+            (None, Some(search)) => self.state.matches(&search.query, lines),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    pub fn key(&mut self, key: Key) -> Outcome {
+        self.message = None;
+
+        if self.prompt.is_some() {
+            self.prompt_key(key);
+            self.scroll_to_cursor();
+
+            return Outcome::Continue;
+        }
+
+        let command = match self.keys.command(key) {
+            Some(c) => c,
+            None => return Outcome::Continue,
+        };
+
+        let (line, col) = self.cursor;
+        let half = u64::from(cmp::max(self.height / 2, 1));
+        let page = u64::from(self.height);
+
+        match command {
+            Command::Left => self.cursor.1 = col.saturating_sub(1),
+            Command::Right => self.cursor.1 = col.saturating_add(1),
+            Command::Up => self.cursor.0 = line.saturating_sub(1),
+            Command::Down => self.cursor.0 = line.saturating_add(1),
+            Command::WordForward => self.cursor = self.next_word(),
+            Command::WordBackward => self.cursor = self.previous_word(),
+            Command::LineStart => self.cursor.1 = 0,
+            Command::LineEnd => {
+                let len = self.chars(line).len();
+                self.cursor.1 = len.saturating_sub(1) as u16;
+            }
+            Command::Top => self.cursor = (0, 0),
+            Command::Bottom => self.cursor = (u64::MAX, 0),
+            Command::ViewTop => self.cursor.0 = self.top,
+            Command::ViewMiddle => self.cursor.0 = self.top + page / 2,
+            Command::ViewBottom => self.cursor.0 = self.top + page - 1,
+            Command::HalfPageUp => self.scroll_up(half),
+            Command::HalfPageDown => self.scroll_down(half),
+            Command::PageUp => self.scroll_up(page),
+            Command::PageDown => self.scroll_down(page),
+            Command::Select(kind) => {
+                self.selection = match self.selection {
+                    Some((k, _)) if k == kind => None,
+                    Some((_, anchor)) => Some((kind, anchor)),
+                    None => Some((kind, self.cursor)),
+                };
+            }
+            Command::ClearSelection => self.selection = None,
+            Command::Search(direction) => {
+                self.prompt = Some(Prompt {
+                    direction,
+                    text: String::new(),
+                    regex: false,
+                    origin: self.cursor,
+                });
+            }
+            Command::SearchAgain => self.search_again(false),
+            Command::SearchReverse => self.search_again(true),
+            Command::Yank => {
+                return match self.selected_text() {
+                    Some(text) if !text.is_empty() => Outcome::Yank(text),
+                    _ => Outcome::Exit,
+                };
+            }
+            Command::Cancel => return Outcome::Exit,
+        }
+
+        self.clamp();
+        self.scroll_to_cursor();
+
+        Outcome::Continue
+    }
+
+    /// Handle a mouse event. The wheel scrolls, leaving copy mode when it
+    /// rolls past the bottom, and dragging with the left button selects text,
+    /// which is yanked when the button is let go.
+    pub fn mouse(&mut self, event: &MouseEvent) -> Outcome {
+        let (row, col) = event.position;
+        let position = (self.top + u64::from(row.0), col.0);
+
+        match (event.kind, event.button) {
+            (MouseKind::Press, Some(MouseButton::WheelUp)) => self.scroll_up(WHEEL_LINES),
+            (MouseKind::Press, Some(MouseButton::WheelDown)) => {
+                let bottom = self.top + u64::from(self.height) >= self.state.line_range().end;
+
+                if bottom && self.selection.is_none() {
+                    return Outcome::Exit;
+                }
+
+                self.scroll_down(WHEEL_LINES);
+            }
+            (MouseKind::Press, Some(MouseButton::Left)) => {
+                self.cursor = position;
+                self.selection = None;
+                self.dragging = false;
+            }
+            (MouseKind::Drag, Some(MouseButton::Left)) => {
+                if !self.dragging {
+                    self.selection = Some((SelectionKind::Char, self.cursor));
+                    self.dragging = true;
+                }
+
+                self.cursor = position;
+            }
+            (MouseKind::Release, _) if self.dragging => {
+                self.dragging = false;
+
+                return match self.selected_text() {
+                    Some(text) if !text.is_empty() => Outcome::Yank(text),
+                    _ => Outcome::Continue,
+                };
+            }
+            _ => return Outcome::Continue,
+        }
+
+        self.clamp();
+        self.scroll_to_cursor();
+
+        Outcome::Continue
+    }
+
+    /// The selected text. Lines are joined with newlines, except where a line
+    /// was soft-wrapped, and trailing blanks are dropped.
+    pub fn selected_text(&self) -> Option<String> {
+        let (kind, anchor) = self.selection?;
+        let (start, end) = ordered(anchor, self.cursor);
+
+        let left = cmp::min(anchor.1, self.cursor.1) as usize;
+        let right = cmp::max(anchor.1, self.cursor.1) as usize + 1;
+
+        let mut text = String::new();
+
+        for line in start.0..=end.0 {
+            let wrapped = kind != SelectionKind::Block
+                && line != end.0
+                && self.state.line(line).is_some_and(|l| l.is_wrapped());
+
+            let chars = self.chars(line);
+
+            let (from, to) = match kind {
+                SelectionKind::Char if line == start.0 && line == end.0 => {
+                    (start.1 as usize, end.1 as usize + 1)
+                }
+                SelectionKind::Char if line == start.0 => (start.1 as usize, chars.len()),
+                SelectionKind::Char if line == end.0 => (0, end.1 as usize + 1),
+                SelectionKind::Char | SelectionKind::Line => (0, chars.len()),
+                SelectionKind::Block => (left, right),
+            };
+
+            let to = cmp::min(to, chars.len());
+            let from = cmp::min(from, to);
+            let piece: String = chars[from..to].iter().collect();
+
+            if wrapped {
+                text.push_str(&piece);
+            } else {
+                text.push_str(piece.trim_end());
+
+                if line != end.0 {
+                    text.push('\n');
+                }
+            }
+        }
+
+        Some(text)
+    }
+
+    /// Edit the search prompt, moving the cursor to the first match as the
+    /// query is typed.
+    fn prompt_key(&mut self, key: Key) {
+        let prompt = match self.prompt.as_mut() {
+            Some(p) => p,
+            None => return,
+        };
+
+        match key {
+            Key::Char('\n') => {
+                let prompt = self.prompt.take().unwrap();
+
+                if prompt.text.is_empty() {
+                    return;
+                }
+
+                match prompt_query(&prompt) {
+                    Ok(query) => {
+                        self.search = Some(Search {
+                            query,
+                            text: prompt.text,
+                            direction: prompt.direction,
+                        });
+
+                        // Report wrapping or a failed search.
+                        self.cursor = prompt.origin;
+                        self.search_again(false);
+                    }
+                    Err(_) => {
+                        self.cursor = prompt.origin;
+                        self.message = Some(format!("Invalid regex: {}", prompt.text));
+                    }
+                }
+
+                return;
+            }
+            Key::Esc | Key::Ctrl('g') | Key::Ctrl('c') => {
+                self.cursor = prompt.origin;
+                self.prompt = None;
+                return;
+            }
+            Key::Backspace if prompt.text.is_empty() => {
+                self.cursor = prompt.origin;
+                self.prompt = None;
+                return;
+            }
+            Key::Backspace => {
+                prompt.text.pop();
+            }
+            Key::Ctrl('t') => prompt.regex = !prompt.regex,
+            Key::Char(c) => prompt.text.push(c),
+            _ => return,
+        }
+
+        let prompt = prompt.clone();
+        let from = (prompt.origin.0, Col(prompt.origin.1));
+        let found = prompt_query(&prompt)
+            .ok()
+            .and_then(|query| self.state.search(&query, from, prompt.direction, true));
+
+        self.cursor = match found {
+            Some(m) => (m.start.0, m.start.1 .0),
+            None => prompt.origin,
+        };
+    }
+
+    /// Move to the next match of the last search, in the opposite direction
+    /// if `reverse` is set.
+    fn search_again(&mut self, reverse: bool) {
+        let search = match self.search.as_ref() {
+            Some(s) => s,
+            None => return,
+        };
+
+        let direction = match (search.direction, reverse) {
+            (d, false) => d,
+            (Direction::Forward, true) => Direction::Backward,
+            (Direction::Backward, true) => Direction::Forward,
+        };
+
+        let (line, col) = self.cursor;
+        let from = (line, Col(col));
+
+        match self.state.search(&search.query, from, direction, true) {
+            Some(m) => {
+                let wrapped = match direction {
+                    Direction::Forward => m.start <= from,
+                    Direction::Backward => m.start >= from,
+                };
+
+                if wrapped {
+                    self.message = Some(match direction {
+                        Direction::Forward => "Search hit bottom, continuing at top".into(),
+                        Direction::Backward => "Search hit top, continuing at bottom".into(),
+                    });
+                }
+
+                self.cursor = (m.start.0, m.start.1 .0);
+            }
+            None => self.message = Some(format!("Pattern not found: {}", search.text)),
+        }
+    }
+
+    fn chars(&self, line: u64) -> Vec<char> {
+        self.state
+            .line(line)
+            .map(|l| l.text().chars().collect())
+            .unwrap_or_default()
+    }
+
+    /// The start of the next word after the cursor, or the cursor if there
+    /// isn't one.
+    fn next_word(&self) -> Position {
+        let end = self.state.line_range().end;
+        let (mut line, col) = self.cursor;
+
+        let mut chars = self.chars(line);
+        let mut col = col as usize;
+        let mut in_word = chars.get(col).is_some_and(|c| !c.is_whitespace());
+
+        loop {
+            col += 1;
+
+            if col >= chars.len() {
+                let wrapped = self.state.line(line).is_some_and(|l| l.is_wrapped());
+
+                line += 1;
+                if line >= end {
+                    return self.cursor;
+                }
+
+                chars = self.chars(line);
+                col = 0;
+                in_word &= wrapped;
+            }
+
+            let word = chars.get(col).is_some_and(|c| !c.is_whitespace());
+            if word && !in_word {
+                return (line, col as u16);
+            }
+
+            in_word = word;
+        }
+    }
+
+    /// The start of the word before the cursor, or the cursor if there isn't
+    /// one.
+    fn previous_word(&self) -> Position {
+        let start = self.state.line_range().start;
+        let (mut line, col) = self.cursor;
+
+        let mut chars = self.chars(line);
+        let mut col = col as usize;
+        let mut found = None;
+
+        loop {
+            if col == 0 {
+                if line <= start {
+                    break;
+                }
+
+                // A word can only continue onto the previous line if that
+                // line was soft-wrapped.
+                let wrapped = self.state.line(line - 1).is_some_and(|l| l.is_wrapped());
+                if found.is_some() && !wrapped {
+                    break;
+                }
+
+                line -= 1;
+                chars = self.chars(line);
+                col = chars.len();
+                continue;
+            }
+
+            col -= 1;
+
+            match (chars.get(col).is_some_and(|c| !c.is_whitespace()), found) {
+                (true, _) => found = Some((line, col as u16)),
+                (false, Some(_)) => break,
+                (false, None) => (),
+            }
+        }
+
+        found.unwrap_or(self.cursor)
+    }
+
+    fn scroll_up(&mut self, lines: u64) {
+        self.top = self.top.saturating_sub(lines);
+        self.cursor.0 = self.cursor.0.saturating_sub(lines);
+    }
+
+    fn scroll_down(&mut self, lines: u64) {
+        self.top = self.top.saturating_add(lines);
+        self.cursor.0 = self.cursor.0.saturating_add(lines);
+    }
+
+    fn clamp(&mut self) {
+        let range = self.state.line_range();
+        let last_col = self.state.columns().0.saturating_sub(1);
+
+        self.cursor.0 = cmp::max(range.start, cmp::min(self.cursor.0, range.end - 1));
+        self.cursor.1 = cmp::min(self.cursor.1, last_col);
+    }
+
+    fn scroll_to_cursor(&mut self) {
+        let range = self.state.line_range();
+        let height = u64::from(self.height);
+        let last_top = cmp::max(range.start, range.end.saturating_sub(height));
+
+        self.top = cmp::max(range.start, cmp::min(self.top, last_top));
+
+        if self.cursor.0 < self.top {
+            self.top = self.cursor.0;
+        } else if self.cursor.0 >= self.top + height {
+            self.top = self.cursor.0 + 1 - height;
+        }
+    }
+}
+
+/// Searches are case sensitive only if the query has an upper case letter.
+fn prompt_query(prompt: &Prompt) -> muxr_core::error::Result<Query> {
+    let case_insensitive = !prompt.text.chars().any(char::is_uppercase);
+
+    Query::new(&prompt.text, prompt.regex, case_insensitive)
+}
+
+fn ordered(a: Position, b: Position) -> (Position, Position) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use muxr_core::state::Col;
+
+    fn state(rows: u16, cols: u16, text: &str) -> State {
+        let mut state = State::with_dimensions(Row(rows), Col(cols));
+
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                state.carriage_return();
+                state.linefeed();
+            }
+
+            line.chars().for_each(|c| state.print(c));
+        }
+
+        state
+    }
+
+    fn keys(copy: &mut CopyMode, keys: &[Key]) -> Outcome {
+        let mut outcome = Outcome::Continue;
+
+        for key in keys {
+            outcome = copy.key(*key);
+        }
+
+        outcome
+    }
+
+    #[test]
+    fn yank_char_selection() {
+        let state = state(3, 8, "one two\nthree\nfour");
+        let mut copy = CopyMode::new(state, KeyTable::Vi, Row(3));
+        assert_eq!(copy.cursor(), (2, 4));
+
+        let outcome = keys(
+            &mut copy,
+            &[
+                Key::Char('g'),
+                Key::Char('l'),
+                Key::Char('v'),
+                Key::Char('j'),
+                Key::Char('y'),
+            ],
+        );
+
+        assert_eq!(outcome, Outcome::Yank("ne two\nth".into()));
+    }
+
+    #[test]
+    fn live_resize_during_copy_mode() {
+        let mut live = state(2, 4, "one\ntwo\nsix");
+        let mut copy = CopyMode::new(live.clone(), KeyTable::Vi, Row(2));
+        keys(&mut copy, &[Key::Char('g'), Key::Char('v'), Key::Char('j')]);
+
+        // Resizing re-wraps the live screen's lines, but copy mode works on
+        // its own snapshot, so its positions still point at the same text.
+        live.resize(Row(3), Col(2));
+        copy.set_height(Row(3));
+
+        let outcome = keys(&mut copy, &[Key::Char('y')]);
+        assert_eq!(outcome, Outcome::Yank("one\nt".into()));
+    }
+
+    #[test]
+    fn yank_joins_wrapped_lines() {
+        let state = state(3, 4, "abcdef\ngh");
+        let mut copy = CopyMode::new(state, KeyTable::Vi, Row(3));
+
+        let outcome = keys(
+            &mut copy,
+            &[
+                Key::Char('g'),
+                Key::Char('V'),
+                Key::Char('G'),
+                Key::Char('y'),
+            ],
+        );
+
+        assert_eq!(outcome, Outcome::Yank("abcdef\ngh".into()));
+    }
+
+    #[test]
+    fn yank_block_selection() {
+        let state = state(3, 6, "abcdef\nghijkl\nmnopqr");
+        let mut copy = CopyMode::new(state, KeyTable::Vi, Row(3));
+
+        let outcome = keys(
+            &mut copy,
+            &[
+                Key::Char('g'),
+                Key::Char('l'),
+                Key::Ctrl('v'),
+                Key::Char('j'),
+                Key::Char('l'),
+                Key::Char('l'),
+            ],
+        );
+
+        assert_eq!(outcome, Outcome::Continue);
+        assert!(copy.is_selected(1, 3));
+        assert!(!copy.is_selected(0, 0));
+        assert!(!copy.is_selected(2, 2));
+        assert_eq!(copy.selected_text().unwrap(), "bcd\nhij");
+    }
+
+    #[test]
+    fn word_motions() {
+        let state = state(2, 10, "foo  bar\nbaz");
+        let mut copy = CopyMode::new(state, KeyTable::Vi, Row(2));
+
+        keys(&mut copy, &[Key::Char('g')]);
+        keys(&mut copy, &[Key::Char('w')]);
+        assert_eq!(copy.cursor(), (0, 5));
+        keys(&mut copy, &[Key::Char('w')]);
+        assert_eq!(copy.cursor(), (1, 0));
+        keys(&mut copy, &[Key::Char('b')]);
+        assert_eq!(copy.cursor(), (0, 5));
+        keys(&mut copy, &[Key::Char('b'), Key::Char('b')]);
+        assert_eq!(copy.cursor(), (0, 0));
+    }
+
+    #[test]
+    fn scroll_into_history() {
+        let state = state(2, 4, "a\nb\nc\nd");
+        assert_eq!(state.history().len(), 2);
+
+        let mut copy = CopyMode::new(state, KeyTable::Emacs, Row(2));
+        assert_eq!((copy.top(), copy.cursor()), (2, (3, 1)));
+
+        keys(&mut copy, &[Key::Up, Key::Up]);
+        assert_eq!((copy.top(), copy.cursor()), (1, (1, 1)));
+
+        keys(&mut copy, &[Key::Alt('<')]);
+        assert_eq!((copy.top(), copy.cursor()), (0, (0, 0)));
+
+        keys(&mut copy, &[Key::PageDown]);
+        assert_eq!((copy.top(), copy.cursor()), (2, (2, 0)));
+
+        let outcome = keys(&mut copy, &[Key::Null, Key::Ctrl('e'), Key::Alt('w')]);
+        assert_eq!(outcome, Outcome::Yank("c".into()));
+    }
+
+    #[test]
+    fn cancel_and_empty_yank_exit() {
+        let mut copy = CopyMode::new(state(2, 4, "a"), KeyTable::Emacs, Row(2));
+        assert_eq!(copy.key(Key::Char('x')), Outcome::Continue);
+        assert_eq!(copy.key(Key::Alt('w')), Outcome::Exit);
+        assert_eq!(copy.key(Key::Esc), Outcome::Exit);
+    }
+
+    #[test]
+    fn incremental_search() {
+        let state = state(3, 10, "foo bar\nbaz\nbar");
+        let mut copy = CopyMode::new(state, KeyTable::Vi, Row(3));
+
+        keys(&mut copy, &[Key::Char('g'), Key::Char('/'), Key::Char('b')]);
+        assert_eq!(copy.cursor(), (0, 4));
+        assert_eq!(copy.status().unwrap(), "/b");
+
+        keys(&mut copy, &[Key::Char('a'), Key::Char('z')]);
+        assert_eq!(copy.cursor(), (1, 0));
+        assert_eq!(copy.matches().len(), 1);
+
+        keys(&mut copy, &[Key::Esc]);
+        assert_eq!(copy.cursor(), (0, 0));
+        assert_eq!(copy.status(), None);
+    }
+
+    #[test]
+    fn search_again_wraps() {
+        let state = state(3, 10, "foo bar\nbaz\nbar");
+        let mut copy = CopyMode::new(state, KeyTable::Vi, Row(3));
+
+        keys(&mut copy, &[Key::Char('g'), Key::Char('/')]);
+        keys(&mut copy, &[Key::Char('B'), Key::Char('A'), Key::Char('R')]);
+        keys(&mut copy, &[Key::Char('\n')]);
+        assert_eq!(copy.status().unwrap(), "Pattern not found: BAR");
+
+        keys(&mut copy, &[Key::Char('/'), Key::Ctrl('t'), Key::Char('b')]);
+        keys(&mut copy, &[Key::Char('a'), Key::Char('r'), Key::Char('$')]);
+        assert_eq!(copy.status().unwrap(), "regex /bar$");
+        keys(&mut copy, &[Key::Char('\n')]);
+        assert_eq!(copy.cursor(), (0, 4));
+
+        keys(&mut copy, &[Key::Char('n')]);
+        assert_eq!(copy.cursor(), (2, 0));
+
+        keys(&mut copy, &[Key::Char('n')]);
+        assert_eq!(copy.cursor(), (0, 4));
+        assert!(copy.status().unwrap().contains("hit bottom"));
+
+        keys(&mut copy, &[Key::Char('N')]);
+        assert_eq!(copy.cursor(), (2, 0));
+        assert!(copy.status().unwrap().contains("hit top"));
+    }
+
+    fn mouse(kind: MouseKind, button: MouseButton, row: u16, col: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            button: Some(button),
+            modifiers: Default::default(),
+            position: (Row(row), Col(col)),
+        }
+    }
+
+    #[test]
+    fn drag_to_yank() {
+        let state = state(3, 8, "one two\nthree\nfour");
+        let mut copy = CopyMode::new(state, KeyTable::Emacs, Row(3));
+
+        let press = mouse(MouseKind::Press, MouseButton::Left, 0, 4);
+        assert!(!starts_with(&press));
+        assert_eq!(copy.mouse(&press), Outcome::Continue);
+        assert_eq!(copy.cursor(), (0, 4));
+
+        let drag = mouse(MouseKind::Drag, MouseButton::Left, 1, 2);
+        assert!(starts_with(&drag));
+        assert_eq!(copy.mouse(&drag), Outcome::Continue);
+        assert!(copy.is_selected(0, 6));
+
+        let release = mouse(MouseKind::Release, MouseButton::Left, 1, 2);
+        assert_eq!(copy.mouse(&release), Outcome::Yank("two\nthr".into()));
+
+        // A click without a drag doesn't yank.
+        copy.mouse(&press);
+        assert_eq!(copy.mouse(&release), Outcome::Continue);
+    }
+
+    #[test]
+    fn wheel_scrolls() {
+        let state = state(2, 4, "a\nb\nc\nd\ne\nf");
+        let mut copy = CopyMode::new(state, KeyTable::Vi, Row(2));
+        assert_eq!(copy.top(), 4);
+
+        let up = mouse(MouseKind::Press, MouseButton::WheelUp, 0, 0);
+        let down = mouse(MouseKind::Press, MouseButton::WheelDown, 0, 0);
+        assert!(starts_with(&up));
+        assert!(!starts_with(&down));
+
+        copy.mouse(&up);
+        assert_eq!(copy.top(), 1);
+
+        assert_eq!(copy.mouse(&down), Outcome::Continue);
+        assert_eq!(copy.top(), 4);
+
+        // Rolling past the bottom leaves copy mode.
+        assert_eq!(copy.mouse(&down), Outcome::Exit);
+    }
+}
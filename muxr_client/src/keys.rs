@@ -0,0 +1,197 @@
+use muxr_core::input::{Key, KeyEvent, KeyKind, Modifiers};
+
+use termion::event::Key as TKey;
+
+/// Translate a key termion understood. Termion folds Ctrl and Alt into the
+/// key itself, and reports Enter and Tab as characters.
+pub fn from_termion(key: TKey) -> Option<KeyEvent> {
+    let event = match key {
+        TKey::Backspace => Key::Backspace.into(),
+        TKey::Left => Key::Left.into(),
+        TKey::Right => Key::Right.into(),
+        TKey::Up => Key::Up.into(),
+        TKey::Down => Key::Down.into(),
+        TKey::Home => Key::Home.into(),
+        TKey::End => Key::End.into(),
+        TKey::PageUp => Key::PageUp.into(),
+        TKey::PageDown => Key::PageDown.into(),
+        TKey::BackTab => KeyEvent::new(Key::Tab, Modifiers::SHIFT),
+        TKey::Delete => Key::Delete.into(),
+        TKey::Insert => Key::Insert.into(),
+        TKey::F(n) => Key::F(n).into(),
+        TKey::Char(c) => char_key(c).into(),
+        TKey::Alt(c) => KeyEvent::new(char_key(c), Modifiers::ALT),
+        TKey::Ctrl(c) => {
+            // Termion reports 0x1c-0x1f as Ctrl with the digit xterm also
+            // sends them for.
+            let c = match c {
+                '4' => '\\',
+                '5' => ']',
+                '6' => '^',
+                '7' => '_',
+                c => c,
+            };
+
+            KeyEvent::new(Key::Char(c), Modifiers::CTRL)
+        }
+        TKey::Null => KeyEvent::new(Key::Char(' '), Modifiers::CTRL),
+        TKey::Esc => Key::Esc.into(),
+        _ => return None,
+    };
+
+    Some(event)
+}
+
+fn char_key(c: char) -> Key {
+    match c {
+        '\n' | '\r' => Key::Enter,
+        '\t' => Key::Tab,
+        '\x7f' => Key::Backspace,
+        '\x1b' => Key::Esc,
+        c => Key::Char(c),
+    }
+}
+
+/// Parse a key sequence termion doesn't understand. These are the modified
+/// keys xterm sends, like `CSI 1 ; 5 A` for Ctrl-Up, its modifyOtherKeys form
+/// `CSI 27 ; m ; code ~`, and `CSI code ; m u`, which can also say whether the
+/// key was repeated or released.
+pub fn parse(bytes: &[u8]) -> Option<KeyEvent> {
+    let body = bytes.strip_prefix(b"\x1b[")?;
+    let (&last, params) = body.split_last()?;
+
+    let params = std::str::from_utf8(params).ok()?;
+    let mut params = params.split(';');
+
+    let first: u32 = params.next()?.parse().ok()?;
+
+    // The modifiers may be followed by the event kind.
+    let mut modifier = params.next().unwrap_or("1").split(':');
+    let modifiers = modifiers(modifier.next()?.parse().ok()?)?;
+    let kind = match modifier.next() {
+        None | Some("1") => KeyKind::Press,
+        Some("2") => KeyKind::Repeat,
+        Some("3") => KeyKind::Release,
+        Some(_) => return None,
+    };
+
+    let key = match (last, first) {
+        (b'A', 1) => Key::Up,
+        (b'B', 1) => Key::Down,
+        (b'C', 1) => Key::Right,
+        (b'D', 1) => Key::Left,
+        (b'H', 1) => Key::Home,
+        (b'F', 1) => Key::End,
+        (b'P'..=b'S', 1) => Key::F(last - b'P' + 1),
+        (b'~', 27) => code_key(params.next()?.parse().ok()?)?,
+        (b'~', n) => tilde_key(n)?,
+        (b'u', code) => code_key(code)?,
+        _ => return None,
+    };
+
+    Some(KeyEvent {
+        key,
+        modifiers,
+        kind,
+    })
+}
+
+/// The modifiers in xterm's modifier parameter.
+fn modifiers(parameter: u8) -> Option<Modifiers> {
+    Some(Modifiers::from_bits_truncate(parameter.checked_sub(1)?))
+}
+
+fn tilde_key(n: u32) -> Option<Key> {
+    let key = match n {
+        1 | 7 => Key::Home,
+        2 => Key::Insert,
+        3 => Key::Delete,
+        4 | 8 => Key::End,
+        5 => Key::PageUp,
+        6 => Key::PageDown,
+        11..=15 => Key::F(n as u8 - 10),
+        17..=21 => Key::F(n as u8 - 11),
+        23..=24 => Key::F(n as u8 - 12),
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+fn code_key(code: u32) -> Option<Key> {
+    let key = match code {
+        8 | 127 => Key::Backspace,
+        9 => Key::Tab,
+        13 => Key::Enter,
+        27 => Key::Esc,
+        code => Key::Char(std::char::from_u32(code)?),
+    };
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn termion_keys() {
+        let ctrl = |c| Some(KeyEvent::new(Key::Char(c), Modifiers::CTRL));
+
+        assert_eq!(from_termion(TKey::Char('\n')), Some(Key::Enter.into()));
+        assert_eq!(from_termion(TKey::Ctrl('c')), ctrl('c'));
+        assert_eq!(from_termion(TKey::Ctrl('5')), ctrl(']'));
+        assert_eq!(from_termion(TKey::Null), ctrl(' '));
+        assert_eq!(
+            from_termion(TKey::Alt('\r')),
+            Some(KeyEvent::new(Key::Enter, Modifiers::ALT))
+        );
+        assert_eq!(
+            from_termion(TKey::BackTab),
+            Some(KeyEvent::new(Key::Tab, Modifiers::SHIFT))
+        );
+    }
+
+    #[test]
+    fn modified_keys() {
+        let ctrl_shift = Modifiers::CTRL | Modifiers::SHIFT;
+
+        assert_eq!(
+            parse(b"\x1b[1;5C"),
+            Some(KeyEvent::new(Key::Right, Modifiers::CTRL))
+        );
+        assert_eq!(
+            parse(b"\x1b[1;6D"),
+            Some(KeyEvent::new(Key::Left, ctrl_shift))
+        );
+        assert_eq!(
+            parse(b"\x1b[15;3~"),
+            Some(KeyEvent::new(Key::F(5), Modifiers::ALT))
+        );
+        assert_eq!(
+            parse(b"\x1b[1;9Q"),
+            Some(KeyEvent::new(Key::F(2), Modifiers::SUPER))
+        );
+        assert_eq!(
+            parse(b"\x1b[27;6;65~"),
+            Some(KeyEvent::new(Key::Char('A'), ctrl_shift))
+        );
+        assert_eq!(
+            parse(b"\x1b[13;5u"),
+            Some(KeyEvent::new(Key::Enter, Modifiers::CTRL))
+        );
+
+        let mut release = KeyEvent::new(Key::Char('a'), Modifiers::CTRL);
+        release.kind = KeyKind::Release;
+        assert_eq!(parse(b"\x1b[97;5:3u"), Some(release));
+    }
+
+    #[test]
+    fn unknown_sequences() {
+        assert_eq!(parse(b"\x1b[200~"), None);
+        assert_eq!(parse(b"\x1b[1;0A"), None);
+        assert_eq!(parse(b"\x1b[1;5Z"), None);
+        assert_eq!(parse(b"\x1bOx"), None);
+        assert_eq!(parse(b""), None);
+    }
+}
@@ -1,54 +1,141 @@
 #[macro_use]
 extern crate error_chain;
 
+mod copy;
 mod error;
 mod io;
+mod keys;
+mod mouse;
 mod render;
 
+use crate::copy::{CopyMode, KeyTable, Outcome};
 use crate::error::Result;
 use crate::io::{split, ReadHalf, WriteHalf};
 
 use crossbeam_channel::{bounded, select};
 
-use muxr_core::state::{Col, Row, State};
+use muxr_core::input::{Modifiers, MouseEvent};
+use muxr_core::msg::{ClientMessage, ServerMessage};
+use muxr_core::state::{Col, MouseTracking, Row, State};
+
+use signal_hook::iterator::Signals;
+use signal_hook::SIGWINCH;
 
 use std::fs::File;
 use std::io::Write;
 use std::os::unix::net::UnixStream;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use termion::event::{Event, Key};
-use termion::input::TermRead;
+use termion::input::TermReadEventsAndRaw;
 use termion::raw::{IntoRawMode, RawTerminal};
 use termion::{get_tty, terminal_size};
 
+const BRACKETED_PASTE_ENABLE: &str = "\x1b[?2004h";
+const BRACKETED_PASTE_DISABLE: &str = "\x1b[?2004l";
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+const FOCUS_ENABLE: &str = "\x1b[?1004h";
+const FOCUS_DISABLE: &str = "\x1b[?1004l";
+const FOCUS_IN: &[u8] = b"\x1b[I";
+const FOCUS_OUT: &[u8] = b"\x1b[O";
+
+/// The connection to the server. Messages are sent from several threads, so
+/// each one is written while holding the lock to keep it in one piece.
+#[derive(Debug)]
+struct Connection {
+    read: UnixStream,
+    write: Mutex<UnixStream>,
+}
+
+impl Connection {
+    fn new(stream: UnixStream) -> Result<Self> {
+        Ok(Connection {
+            write: Mutex::new(stream.try_clone()?),
+            read: stream,
+        })
+    }
+}
+
 pub fn run() -> Result<()> {
     let running = Arc::new(AtomicBool::new(true));
-    let stream = Arc::new(UnixStream::connect("/tmp/muxr.sock")?);
+    let stream = Arc::new(Connection::new(UnixStream::connect("/tmp/muxr.sock")?)?);
 
     let tty = get_tty().unwrap();
     let (read, write) = split(tty);
 
-    let raw = write.into_raw_mode()?;
+    let mut raw = write.into_raw_mode()?;
+
+    // Have pastes bracketed, so they can be sent as one event, capture the
+    // mouse, and hear about focus changes.
+    write!(
+        raw,
+        "{}{}{}",
+        BRACKETED_PASTE_ENABLE,
+        mouse::ENABLE,
+        FOCUS_ENABLE
+    )?;
+    raw.flush()?;
+
+    let size = Arc::new(AtomicU32::new(0));
+    let signals = Signals::new([SIGWINCH])?;
+
+    send_resize(&stream, &size)?;
+
+    let stream_clone = stream.clone();
+    let size_clone = size.clone();
+    thread::Builder::new()
+        .name("resize".into())
+        .spawn(move || {
+            for _ in signals.forever() {
+                if send_resize(&stream_clone, &size_clone).is_err() {
+                    break;
+                }
+            }
+        })?;
+
+    let copy = Arc::new(Mutex::new(None));
+    let keys = KeyTable::from_env();
+    let mouse = Arc::new(mouse::Shared::default());
 
     let (s0, r0) = bounded::<()>(0);
     let (s1, r1) = bounded::<()>(0);
 
     let stream_clone = stream.clone();
+    let copy_clone = copy.clone();
+    let mouse_clone = mouse.clone();
     let running_clone = running.clone();
     let h0 = thread::Builder::new()
         .name("output".into())
         .spawn(move || {
-            let result = output_loop(stream_clone, raw, running_clone);
+            let result = output_loop(
+                stream_clone,
+                &mut raw,
+                size,
+                copy_clone,
+                mouse_clone,
+                keys,
+                running_clone,
+            );
+            let _ = write!(
+                raw,
+                "{}{}{}",
+                BRACKETED_PASTE_DISABLE,
+                mouse::DISABLE,
+                FOCUS_DISABLE
+            )
+            .and_then(|_| raw.flush());
             drop(s0);
             result
         })?;
 
     let running_clone = running.clone();
     let h1 = thread::Builder::new().name("input".into()).spawn(move || {
-        let result = input_loop(stream, read, running_clone);
+        let result = input_loop(stream, read, copy, mouse, running_clone);
         drop(s1);
         result
     })?;
@@ -72,20 +159,122 @@ pub fn run() -> Result<()> {
 }
 
 fn output_loop(
-    stream: Arc<UnixStream>,
-    mut raw: RawTerminal<WriteHalf<File>>,
+    stream: Arc<Connection>,
+    raw: &mut RawTerminal<WriteHalf<File>>,
+    size: Arc<AtomicU32>,
+    copy: Arc<Mutex<Option<CopyMode>>>,
+    mouse: Arc<mouse::Shared>,
+    keys: KeyTable,
     running: Arc<AtomicBool>,
 ) -> Result<()> {
-    let (cols, rows) = terminal_size().unwrap();
+    let mut bells = None;
+    let mut status: Option<(String, Instant)> = None;
+    let mut latest = None;
+
+    const STATUS_DURATION: Duration = Duration::from_secs(3);
 
     loop {
-        let state: State = muxr_core::msg::deserialize_from(&mut &*stream)?;
+        let msg: ServerMessage = muxr_core::msg::deserialize_from(&mut &stream.read)?;
 
         if !running.load(Ordering::Relaxed) {
             break;
         }
 
-        render::render(&state, &mut raw, Row(rows), Col(cols)).unwrap();
+        let (rows, cols) = unpack_size(size.load(Ordering::Relaxed));
+
+        match msg {
+            ServerMessage::State(state) => {
+                // Only ring for bells that happened after we attached.
+                if bells.is_some() && bells != Some(state.bell_count()) {
+                    write!(raw, "\x07")?;
+                }
+
+                bells = Some(state.bell_count());
+
+                let child = state.mouse_tracking() != MouseTracking::Off;
+                mouse.child.store(child, Ordering::Relaxed);
+
+                latest = Some(state);
+            }
+            ServerMessage::History(history) => {
+                // Copy mode asked for this, and works on a snapshot of the
+                // screen as it is now.
+                let mut copy = copy.lock().unwrap();
+                let mut pending = mouse.pending.lock().unwrap();
+
+                if let Some(state) = latest.as_ref() {
+                    let mut snapshot = State::clone(state);
+                    snapshot.set_history(history);
+
+                    *copy = Some(CopyMode::new(snapshot, keys, Row(rows)));
+                }
+
+                // Replay the mouse events that started copy mode.
+                for event in pending.drain(..) {
+                    let outcome = match copy.as_mut() {
+                        Some(mode) => mode.mouse(&event),
+                        None => break,
+                    };
+
+                    finish(&stream, &mut copy, outcome)?;
+                }
+            }
+            ServerMessage::Stats(stats) => {
+                let text = format!(
+                    "unhandled: {} execute, {} csi, {} esc, {} osc, {} dcs",
+                    stats.unhandled_execute,
+                    stats.unhandled_csi,
+                    stats.unhandled_esc,
+                    stats.unhandled_osc,
+                    stats.unhandled_dcs,
+                );
+                status = Some((text, Instant::now()));
+            }
+            ServerMessage::Buffers(list) => {
+                let text = if list.is_empty() {
+                    "no buffers".into()
+                } else {
+                    list.iter()
+                        .map(|b| format!("{} ({}): {}", b.name, b.size, printable(&b.sample)))
+                        .collect::<Vec<_>>()
+                        .join(" | ")
+                };
+                status = Some((text, Instant::now()));
+            }
+            ServerMessage::Buffer(data) => {
+                let text = match data {
+                    Some(data) => printable(&data),
+                    None => "no buffer".into(),
+                };
+                status = Some((text, Instant::now()));
+            }
+            ServerMessage::Clipboard(selection, data) => {
+                render::clipboard(&selection, &data, raw)?;
+            }
+        }
+
+        let state = match latest.as_ref() {
+            Some(s) => s,
+            None => continue,
+        };
+
+        let mut copy = copy.lock().unwrap();
+
+        if let Some(mode) = copy.as_mut() {
+            mode.set_height(Row(rows));
+        }
+
+        render::render(state, copy.as_ref(), raw, Row(rows), Col(cols)).unwrap();
+
+        if let Some(text) = copy.as_ref().and_then(|c| c.status()) {
+            render::status(&text, raw, Row(rows), Col(cols)).unwrap();
+        } else if let Some((text, _)) = status
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() < STATUS_DURATION)
+        {
+            render::status(text, raw, Row(rows), Col(cols)).unwrap();
+        }
+
         raw.flush().unwrap();
     }
 
@@ -93,31 +282,82 @@ fn output_loop(
 }
 
 fn input_loop(
-    stream: Arc<UnixStream>,
+    stream: Arc<Connection>,
     raw: ReadHalf<File>,
+    copy: Arc<Mutex<Option<CopyMode>>>,
+    mouse: Arc<mouse::Shared>,
     running: Arc<AtomicBool>,
 ) -> Result<()> {
     let mut escape = false;
+    let mut paste: Option<Vec<u8>> = None;
 
-    for event in raw.events() {
+    for item in raw.events_and_raw() {
         if !running.load(Ordering::Relaxed) {
             break;
         }
 
-        let event = event?;
+        let (event, bytes) = item?;
+
+        // Collect everything between the paste markers as it was sent, rather
+        // than as key presses.
+        if let Some(mut data) = paste.take() {
+            if bytes != PASTE_END {
+                data.extend(bytes);
+                paste = Some(data);
+            } else {
+                // Pasting is meant for the child, so it ends copy mode
+                // rather than being lost.
+                *copy.lock().unwrap() = None;
+
+                let text = String::from_utf8_lossy(&data).into_owned();
+                let event = muxr_core::input::Event::Paste(text);
+
+                send(&stream, &ClientMessage::Event(event))?;
+            }
+
+            continue;
+        }
+
+        if bytes == PASTE_START {
+            paste = Some(Vec::new());
+            continue;
+        }
+
+        if bytes == FOCUS_IN || bytes == FOCUS_OUT {
+            let event = muxr_core::input::Event::Focus(bytes == FOCUS_IN);
+            send(&stream, &ClientMessage::Event(event))?;
+            continue;
+        }
+
+        if let Some(event) = mouse::parse(&bytes) {
+            mouse_event(&stream, &copy, &mouse, event)?;
+            continue;
+        }
 
         let escaped = escape;
         escape = false;
 
+        let copying = copy.lock().unwrap().is_some();
+
         match (escaped, event) {
             (false, Event::Key(Key::Alt('!'))) => {
                 escape = true;
             }
-            (false, event) => send_event(&*stream, event)?,
-            (true, Event::Key(Key::Alt('!'))) => send_event(&*stream, Event::Key(Key::Alt('!')))?,
+            (false, Event::Key(key)) if copying => copy_key(&stream, &copy, key)?,
+            (false, _) if copying => (),
+            (false, event) => send_event(&stream, event)?,
+            (true, Event::Key(Key::Alt('!'))) => send_event(&stream, Event::Key(Key::Alt('!')))?,
             (true, Event::Key(Key::Char('q'))) => {
                 break;
             }
+            (true, Event::Key(Key::Char('s'))) => send(&stream, &ClientMessage::QueryStats)?,
+            (true, Event::Key(Key::Char('['))) => send(&stream, &ClientMessage::QueryHistory)?,
+            (true, Event::Key(Key::Char(']'))) => send(&stream, &ClientMessage::PasteBuffer(None))?,
+            (true, Event::Key(Key::Char('#'))) => send(&stream, &ClientMessage::ListBuffers)?,
+            (true, Event::Key(Key::Char('='))) => send(&stream, &ClientMessage::ShowBuffer(None))?,
+            (true, Event::Key(Key::Char('-'))) => {
+                send(&stream, &ClientMessage::DeleteBuffer(None))?
+            }
             (true, Event::Key(_)) => {
                 // TODO: Handle unknown escape sequences.
             }
@@ -128,37 +368,115 @@ fn input_loop(
     Ok(())
 }
 
-fn send_event(mut stream: &UnixStream, event: Event) -> Result<()> {
+fn copy_key(stream: &Connection, copy: &Mutex<Option<CopyMode>>, key: Key) -> Result<()> {
+    let mut copy = copy.lock().unwrap();
+
+    let outcome = match copy.as_mut() {
+        Some(mode) => mode.key(key),
+        None => return Ok(()),
+    };
+
+    finish(stream, &mut copy, outcome)
+}
+
+/// Send a mouse event to the child if it asked for them, and otherwise use
+/// it to start or drive copy mode. Holding Shift keeps events from the child.
+fn mouse_event(
+    stream: &Connection,
+    copy: &Mutex<Option<CopyMode>>,
+    mouse: &mouse::Shared,
+    event: MouseEvent,
+) -> Result<()> {
+    let mut copy = copy.lock().unwrap();
+
+    if let Some(mode) = copy.as_mut() {
+        let outcome = mode.mouse(&event);
+        return finish(stream, &mut copy, outcome);
+    }
+
+    // Copy mode has been asked for, and the rest of the gesture belongs to it.
+    let mut pending = mouse.pending.lock().unwrap();
+    if !pending.is_empty() {
+        pending.push(event);
+        return Ok(());
+    }
+
+    let ours = !mouse.child.load(Ordering::Relaxed) || event.modifiers.contains(Modifiers::SHIFT);
+
+    if !ours {
+        let event = muxr_core::input::Event::Mouse(event);
+        send(stream, &ClientMessage::Event(event))
+    } else if let Some(events) = mouse.start_copy(event) {
+        pending.extend(events);
+        send(stream, &ClientMessage::QueryHistory)
+    } else {
+        Ok(())
+    }
+}
+
+/// Leave copy mode if it's done, sending any yanked text to the server's
+/// paste buffers.
+fn finish(stream: &Connection, copy: &mut Option<CopyMode>, outcome: Outcome) -> Result<()> {
+    match outcome {
+        Outcome::Continue => Ok(()),
+        Outcome::Exit => {
+            *copy = None;
+            Ok(())
+        }
+        Outcome::Yank(text) => {
+            *copy = None;
+            send(stream, &ClientMessage::SetBuffer(None, text))
+        }
+    }
+}
+
+fn send_event(stream: &Connection, event: Event) -> Result<()> {
     let key = match event {
-        Event::Key(key) => key,
-        _ => return Ok(()),
+        Event::Key(key) => keys::from_termion(key),
+        Event::Unsupported(bytes) => keys::parse(&bytes),
+        Event::Mouse(_) => None,
     };
 
-    let mevent = muxr_core::input::Event::Key(match key {
-        Key::Backspace => muxr_core::input::Key::Backspace,
-        Key::Left => muxr_core::input::Key::Left,
-        Key::Right => muxr_core::input::Key::Right,
-        Key::Up => muxr_core::input::Key::Up,
-        Key::Down => muxr_core::input::Key::Down,
-        Key::Home => muxr_core::input::Key::Home,
-        Key::End => muxr_core::input::Key::End,
-        Key::PageUp => muxr_core::input::Key::PageUp,
-        Key::PageDown => muxr_core::input::Key::PageDown,
-        Key::Delete => muxr_core::input::Key::Delete,
-        Key::Insert => muxr_core::input::Key::Insert,
-        Key::F(u) => muxr_core::input::Key::F(u),
-        Key::Char(c) => muxr_core::input::Key::Char(c),
-        Key::Alt(c) => muxr_core::input::Key::Alt(c),
-        Key::Ctrl(c) => muxr_core::input::Key::Ctrl(c),
-        Key::Null => muxr_core::input::Key::Null,
-        Key::Esc => muxr_core::input::Key::Esc,
-
-        _ => return Ok(()),
-    });
-
-    let bytes = muxr_core::msg::serialize(&mevent)?;
-
-    stream.write_all(&bytes)?;
+    match key {
+        Some(key) => send(
+            stream,
+            &ClientMessage::Event(muxr_core::input::Event::Key(key)),
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Tell the server the terminal's current size, and remember it for
+/// rendering. The size is packed into one integer so it can be shared
+/// without a lock.
+fn send_resize(stream: &Connection, size: &AtomicU32) -> Result<()> {
+    let (cols, rows) = terminal_size()?;
+
+    size.store(u32::from(rows) << 16 | u32::from(cols), Ordering::Relaxed);
+
+    send(stream, &ClientMessage::Resize(Row(rows), Col(cols)))
+}
+
+/// Make buffer contents fit on the status line, showing control characters
+/// the way `cat -v` does.
+fn printable(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            c if c.is_control() && (c as u32) < 0x20 => format!("^{}", (c as u8 + b'@') as char),
+            c if c.is_control() => "?".into(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn unpack_size(size: u32) -> (u16, u16) {
+    ((size >> 16) as u16, size as u16)
+}
+
+fn send(stream: &Connection, msg: &ClientMessage) -> Result<()> {
+    let bytes = muxr_core::msg::serialize(msg)?;
+
+    stream.write.lock().unwrap().write_all(&bytes)?;
 
     Ok(())
 }
@@ -0,0 +1,168 @@
+use crate::copy;
+
+use muxr_core::input::{Modifiers, MouseButton, MouseEvent, MouseKind};
+use muxr_core::state::{Col, Row};
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
+
+/// Reported in any motion mode, with SGR encoding so there's no limit on
+/// coordinates and releases say which button was let go. The server decides
+/// which events the child actually wants.
+pub const ENABLE: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1003h\x1b[?1006h";
+pub const DISABLE: &str = "\x1b[?1006l\x1b[?1003l\x1b[?1002l\x1b[?1000l";
+
+/// What the input and output threads share about the mouse.
+#[derive(Debug, Default)]
+pub struct Shared {
+    /// Whether the child has asked for mouse events, so they aren't muxr's to
+    /// use.
+    pub child: AtomicBool,
+
+    /// Events that started copy mode, kept until the scrollback arrives and
+    /// copy mode can handle them.
+    pub pending: Mutex<Vec<MouseEvent>>,
+
+    /// The last left press muxr kept from the child, so a drag that follows
+    /// can start selecting from where it began.
+    press: Mutex<Option<MouseEvent>>,
+}
+
+impl Shared {
+    /// The events to give copy mode if `event`, which the child isn't getting,
+    /// starts it. A left press only starts copy mode once it's dragged, so a
+    /// plain click does nothing.
+    pub fn start_copy(&self, event: MouseEvent) -> Option<Vec<MouseEvent>> {
+        let mut press = self.press.lock().unwrap();
+
+        if event.kind == MouseKind::Press && event.button == Some(MouseButton::Left) {
+            *press = Some(event);
+            return None;
+        }
+
+        let pressed = press.take();
+
+        if !copy::starts_with(&event) {
+            return None;
+        }
+
+        let mut events: Vec<_> = pressed
+            .filter(|_| event.kind == MouseKind::Drag)
+            .into_iter()
+            .collect();
+        events.push(event);
+
+        Some(events)
+    }
+}
+
+/// Parse an SGR mouse report, `CSI < b ; x ; y M`, or `m` for a release.
+/// Termion understands these, but drops the modifiers and plain motion.
+pub fn parse(bytes: &[u8]) -> Option<MouseEvent> {
+    let body = bytes.strip_prefix(b"\x1b[<")?;
+    let (&last, params) = body.split_last()?;
+
+    let params = std::str::from_utf8(params).ok()?;
+    let mut params = params.split(';').map(|p| p.parse::<u16>().ok());
+
+    let code = params.next()??;
+    let x = params.next()??;
+    let y = params.next()??;
+
+    if x == 0 || y == 0 || params.next().is_some() {
+        return None;
+    }
+
+    let button = match (code & 0b11, code & 64 != 0, code & 128 != 0) {
+        (_, _, true) => return None,
+        (0, false, _) => Some(MouseButton::Left),
+        (1, false, _) => Some(MouseButton::Middle),
+        (2, false, _) => Some(MouseButton::Right),
+        (_, false, _) => None,
+        (0, true, _) => Some(MouseButton::WheelUp),
+        (1, true, _) => Some(MouseButton::WheelDown),
+        (2, true, _) => Some(MouseButton::WheelLeft),
+        (_, true, _) => Some(MouseButton::WheelRight),
+    };
+
+    let kind = match (last, code & 32 != 0, button) {
+        (b'm', _, _) => MouseKind::Release,
+        (b'M', false, _) => MouseKind::Press,
+        (b'M', true, Some(_)) => MouseKind::Drag,
+        (b'M', true, None) => MouseKind::Move,
+        _ => return None,
+    };
+
+    let mut modifiers = Modifiers::empty();
+    modifiers.set(Modifiers::SHIFT, code & 4 != 0);
+    modifiers.set(Modifiers::ALT, code & 8 != 0);
+    modifiers.set(Modifiers::CTRL, code & 16 != 0);
+
+    Some(MouseEvent {
+        kind,
+        button,
+        modifiers,
+        position: (Row(y - 1), Col(x - 1)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn click_without_drag() {
+        let shared = Shared::default();
+        let press = parse(b"\x1b[<0;3;2M").unwrap();
+        let release = parse(b"\x1b[<0;3;2m").unwrap();
+        let drag = parse(b"\x1b[<32;5;2M").unwrap();
+
+        assert_eq!(shared.start_copy(press), None);
+        assert_eq!(shared.start_copy(release), None);
+
+        // The press before the release isn't part of a later drag.
+        assert_eq!(shared.start_copy(drag), Some(vec![drag]));
+
+        assert_eq!(shared.start_copy(press), None);
+        assert_eq!(shared.start_copy(drag), Some(vec![press, drag]));
+    }
+
+    #[test]
+    fn buttons_and_motion() {
+        let press = parse(b"\x1b[<0;3;2M").unwrap();
+        assert_eq!(press.kind, MouseKind::Press);
+        assert_eq!(press.button, Some(MouseButton::Left));
+        assert_eq!(press.position, (Row(1), Col(2)));
+
+        let release = parse(b"\x1b[<2;1;1m").unwrap();
+        assert_eq!(release.kind, MouseKind::Release);
+        assert_eq!(release.button, Some(MouseButton::Right));
+
+        let drag = parse(b"\x1b[<32;1;1M").unwrap();
+        assert_eq!(drag.kind, MouseKind::Drag);
+
+        let moved = parse(b"\x1b[<35;1;1M").unwrap();
+        assert_eq!((moved.kind, moved.button), (MouseKind::Move, None));
+
+        let wheel = parse(b"\x1b[<65;1;1M").unwrap();
+        assert_eq!(wheel.button, Some(MouseButton::WheelDown));
+    }
+
+    #[test]
+    fn modifiers() {
+        let event = parse(b"\x1b[<28;1;1M").unwrap();
+        assert_eq!(
+            event.modifiers,
+            Modifiers::SHIFT | Modifiers::ALT | Modifiers::CTRL
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(parse(b"\x1b[<0;0;1M"), None);
+        assert_eq!(parse(b"\x1b[<0;1M"), None);
+        assert_eq!(parse(b"\x1b[<0;1;1;1M"), None);
+        assert_eq!(parse(b"\x1b[<128;1;1M"), None);
+        assert_eq!(parse(b"\x1b[1;5A"), None);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::copy::CopyMode;
 use crate::error::*;
 
 use muxr_core::state::{Cell, CellStyle, Col, Color, Row, State};
@@ -72,12 +73,35 @@ impl WriteDelta for Cell {
     }
 }
 
-pub fn render<W: Write>(state: &State, w: &mut W, rows: Row, cols: Col) -> Result<()> {
+/// Draw `state`, or the snapshot `copy` is looking at if copy mode is active.
+/// Cells selected in copy mode are drawn in reverse video, and search matches
+/// are highlighted.
+pub fn render<W: Write>(
+    state: &State,
+    copy: Option<&CopyMode>,
+    w: &mut W,
+    rows: Row,
+    cols: Col,
+) -> Result<()> {
+    match copy {
+        Some(copy) => render_copy(copy, w, rows, cols),
+        None => render_screen(state, w, rows, cols),
+    }
+}
+
+/// What's drawn where the terminal is bigger than the screen being shown.
+fn out_of_bounds() -> Cell {
     let mut oob = Cell::default();
     oob.style = CellStyle::REVERSE;
     oob.foreground = Color::BLACK;
     oob.background = Color::BLACK;
     oob.content = Some('.');
+    oob
+}
+
+/// Draw the screen straight from its cells.
+fn render_screen<W: Write>(state: &State, w: &mut W, rows: Row, cols: Col) -> Result<()> {
+    let oob = out_of_bounds();
 
     let first = Cell::default();
     let mut prev = &first;
@@ -95,3 +119,80 @@ pub fn render<W: Write>(state: &State, w: &mut W, rows: Row, cols: Col) -> Resul
 
     Ok(())
 }
+
+/// Draw the part of copy mode's snapshot it's scrolled to, which can include
+/// scrollback, so it's drawn line by line.
+fn render_copy<W: Write>(copy: &CopyMode, w: &mut W, rows: Row, cols: Col) -> Result<()> {
+    let oob = out_of_bounds();
+
+    let state = copy.state();
+    let top = copy.top();
+    let end = state.line_range().end;
+    let matches = copy.matches();
+    let highlight = Color::new(0xff, 0xd7, 0x00);
+
+    let mut prev = Cell::default();
+
+    for row in 0..rows.0 {
+        write!(w, "{}", t::cursor::Goto(1, row + 1))?;
+
+        let number = top + u64::from(row);
+        let line = Some(number)
+            .filter(|n| *n < end)
+            .and_then(|n| state.line(n));
+        let mut cells = line.iter().flat_map(|l| l.cells());
+
+        for col in 0..cols.0 {
+            let mut cell = match line {
+                Some(_) if col < state.columns().0 => cells.next().unwrap_or_default(),
+                _ => oob.clone(),
+            };
+
+            if matches.iter().any(|m| m.contains(number, Col(col))) {
+                cell.style.remove(CellStyle::REVERSE);
+                cell.foreground = Color::BLACK;
+                cell.background = highlight;
+            }
+
+            if copy.is_selected(number, col) {
+                cell.style.toggle(CellStyle::REVERSE);
+            }
+
+            cell.write_delta(&prev, w)?;
+            write!(w, "{}", cell.content.unwrap_or(' '))?;
+            prev = cell;
+        }
+    }
+
+    let (line, col) = copy.cursor();
+    let row = (line - top) as u16;
+
+    write!(w, "{}", t::cursor::Goto(col + 1, row + 1))?;
+
+    Ok(())
+}
+
+/// Put `data` on the terminal's clipboard with OSC 52, for the selections
+/// named in `selection`.
+pub fn clipboard<W: Write>(selection: &str, data: &str, w: &mut W) -> Result<()> {
+    write!(w, "\x1b]52;{};{}\x07", selection, base64::encode(data))?;
+    Ok(())
+}
+
+/// Draw `text` in reverse video over the bottom row of the terminal.
+pub fn status<W: Write>(text: &str, w: &mut W, rows: Row, cols: Col) -> Result<()> {
+    let text: String = text.chars().take(cols.0.into()).collect();
+
+    write!(
+        w,
+        "{}{}{}{:width$}{}",
+        t::cursor::Goto(1, rows.0),
+        t::style::Reset,
+        t::style::Invert,
+        text,
+        t::style::Reset,
+        width = cols.0.into(),
+    )?;
+
+    Ok(())
+}
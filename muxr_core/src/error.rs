@@ -2,5 +2,6 @@ error_chain! {
     foreign_links {
         Io(::std::io::Error);
         Bincode(::bincode::Error);
+        Regex(::regex::Error);
     }
 }
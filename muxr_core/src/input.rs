@@ -1,11 +1,65 @@
+use crate::state::{Col, Row};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Event {
-    Key(Key),
+    Key(KeyEvent),
+    /// Text pasted into the client's terminal, without the bracket markers.
+    Paste(String),
+    Mouse(MouseEvent),
+    /// The client's terminal gained or lost focus.
+    Focus(bool),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+bitflags! {
+    /// Modifier keys held during a key event. The bits are the ones xterm
+    /// adds to 1 for the modifier parameter of `CSI 1 ; 5 A` and friends.
+    #[derive(Serialize, Deserialize, Default)]
+    pub struct Modifiers: u8 {
+        const SHIFT = 0b0001;
+        const ALT   = 0b0010;
+        const CTRL  = 0b0100;
+        const SUPER = 0b1000;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum KeyKind {
+    #[default]
+    Press,
+    Repeat,
+    Release,
+}
+
+/// A key being pressed or released, and the modifiers held with it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub modifiers: Modifiers,
+    pub kind: KeyKind,
+}
+
+impl KeyEvent {
+    pub fn new(key: Key, modifiers: Modifiers) -> Self {
+        KeyEvent {
+            key,
+            modifiers,
+            kind: KeyKind::Press,
+        }
+    }
+}
+
+impl From<Key> for KeyEvent {
+    fn from(key: Key) -> Self {
+        KeyEvent::new(key, Modifiers::empty())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Key {
     Backspace,
+    Enter,
+    Tab,
+    Esc,
     Left,
     Right,
     Up,
@@ -17,13 +71,52 @@ pub enum Key {
     Delete,
     Insert,
     F(u8),
+    /// The character the key produces, so Shift-a is `Char('A')` and has
+    /// Shift set as well.
     Char(char),
-    Alt(char),
-    Ctrl(char),
-    Null,
-    Esc,
 
     #[serde(skip)]
     #[doc(hidden)]
     __NonExhaustive,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+}
+
+impl MouseButton {
+    pub fn is_wheel(self) -> bool {
+        !matches!(
+            self,
+            MouseButton::Left | MouseButton::Middle | MouseButton::Right
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MouseKind {
+    Press,
+    Release,
+    /// Motion with a button held.
+    Drag,
+    /// Motion with no buttons held.
+    Move,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MouseEvent {
+    pub kind: MouseKind,
+    /// The button pressed, released or held, if it's known.
+    pub button: Option<MouseButton>,
+    pub modifiers: Modifiers,
+    /// Where the pointer is, counting from zero at the top left of the
+    /// client's terminal.
+    pub position: (Row, Col),
+}
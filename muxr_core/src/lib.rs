@@ -1,10 +1,14 @@
+#![allow(clippy::manual_non_exhaustive)]
+
 #[macro_use]
 extern crate bitflags;
 #[macro_use]
 extern crate derive_more;
 #[macro_use]
 extern crate error_chain;
+#[macro_use]
 extern crate ndarray;
+extern crate regex;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -12,4 +16,6 @@ extern crate serde_derive;
 pub mod error;
 pub mod input;
 pub mod msg;
+pub mod search;
 pub mod state;
+pub mod stats;
@@ -1,10 +1,57 @@
 use crate::error::*;
+use crate::input::Event;
+use crate::state::{Col, History, Row, State};
+use crate::stats::Stats;
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 use std::io::Read;
 
+/// Messages sent from a client to the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClientMessage {
+    Event(Event),
+    /// The client's terminal is now `Row` by `Col`.
+    Resize(Row, Col),
+    QueryStats,
+    /// Ask for the scrollback, which isn't sent with each state.
+    QueryHistory,
+    /// Set the named paste buffer, or push a new one onto the server's paste
+    /// buffer stack if no name is given.
+    SetBuffer(Option<String>, String),
+    ListBuffers,
+    /// Ask for the contents of the named buffer, or the most recent one.
+    ShowBuffer(Option<String>),
+    DeleteBuffer(Option<String>),
+    /// Paste the named buffer, or the most recent one, into the terminal.
+    PasteBuffer(Option<String>),
+}
+
+/// Messages sent from the server to a client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerMessage {
+    State(Box<State>),
+    Stats(Stats),
+    History(History),
+    Buffers(Vec<BufferSummary>),
+    /// The contents of the buffer asked for, if it exists.
+    Buffer(Option<String>),
+    /// Text the child copied with OSC 52, and the selections it names, for
+    /// the client to pass on to its own terminal.
+    Clipboard(String, String),
+}
+
+/// A paste buffer, as listed by the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BufferSummary {
+    pub name: String,
+    /// The length of the buffer in bytes.
+    pub size: usize,
+    /// The start of the buffer.
+    pub sample: String,
+}
+
 pub fn serialize<T: Serialize>(obj: &T) -> Result<Vec<u8>> {
     const USZ_LEN: usize = std::mem::size_of::<usize>();
 
@@ -0,0 +1,214 @@
+use crate::error::*;
+use crate::state::{Col, State};
+
+use regex::{Regex, RegexBuilder};
+
+use std::cmp;
+use std::ops::Range;
+
+/// A line number, as used by `State::line`, and a column.
+pub type Position = (u64, Col);
+
+/// Text to look for in the screen and scrollback.
+#[derive(Debug, Clone)]
+pub struct Query {
+    regex: Regex,
+}
+
+impl Query {
+    /// Look for `text` exactly, or as a regular expression if `regex` is set.
+    pub fn new(text: &str, regex: bool, case_insensitive: bool) -> Result<Self> {
+        let pattern = if regex {
+            text.to_owned()
+        } else {
+            regex::escape(text)
+        };
+
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()?;
+
+        Ok(Query { regex })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+/// Where some text matched a query. A match can continue across lines that
+/// were soft-wrapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    /// The first matching cell.
+    pub start: Position,
+
+    /// The last matching cell.
+    pub end: Position,
+}
+
+impl Match {
+    pub fn contains(&self, line: u64, col: Col) -> bool {
+        self.start <= (line, col) && (line, col) <= self.end
+    }
+}
+
+/// A run of soft-wrapped lines joined together, with the position each
+/// character came from.
+struct Logical {
+    text: String,
+    positions: Vec<(usize, Position)>,
+
+    /// The line after the last one in the run.
+    end: u64,
+}
+
+impl Logical {
+    fn matches(&self, query: &Query) -> Vec<Match> {
+        query
+            .regex
+            .find_iter(&self.text)
+            .filter(|m| !m.is_empty())
+            .map(|m| Match {
+                start: self.position(m.start()),
+                end: self.position(m.end() - 1),
+            })
+            .collect()
+    }
+
+    /// The position of the character containing byte `offset`.
+    fn position(&self, offset: usize) -> Position {
+        let index = match self.positions.binary_search_by_key(&offset, |(o, _)| *o) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+
+        self.positions[index].1
+    }
+}
+
+impl State {
+    /// The nearest match of `query` after `from`, or before it when searching
+    /// backward. With `wrap`, the search carries on from the other end of the
+    /// scrollback and screen instead of giving up.
+    pub fn search(
+        &self,
+        query: &Query,
+        from: Position,
+        direction: Direction,
+        wrap: bool,
+    ) -> Option<Match> {
+        let range = self.line_range();
+        let first = self.logical_start(from.0.clamp(range.start, range.end - 1));
+
+        let beyond = |m: &Match| match direction {
+            Direction::Forward => m.start > from,
+            Direction::Backward => m.start < from,
+        };
+
+        let mut line = first;
+        let mut wrapped = false;
+
+        loop {
+            let logical = self.logical(line);
+            let mut matches = logical.matches(query);
+
+            if direction == Direction::Backward {
+                matches.reverse();
+            }
+
+            // The line the search starts in is visited twice when wrapping:
+            // once for the matches beyond `from`, and last for the rest.
+            let found = match (line == first, wrapped) {
+                (true, false) => matches.into_iter().find(beyond),
+                (true, true) => return matches.into_iter().find(|m| !beyond(m)),
+                (false, _) => matches.into_iter().next(),
+            };
+
+            if found.is_some() {
+                return found;
+            }
+
+            let next = match direction {
+                Direction::Forward if logical.end < range.end => Some(logical.end),
+                Direction::Backward if line > range.start => Some(self.logical_start(line - 1)),
+                _ => None,
+            };
+
+            line = match next {
+                Some(next) => next,
+                None if wrap && !wrapped => {
+                    wrapped = true;
+
+                    match direction {
+                        Direction::Forward => range.start,
+                        Direction::Backward => self.logical_start(range.end - 1),
+                    }
+                }
+                None => return None,
+            };
+        }
+    }
+
+    /// Every match of `query` that touches `lines`, for highlighting.
+    pub fn matches(&self, query: &Query, lines: Range<u64>) -> Vec<Match> {
+        let range = self.line_range();
+        let end = cmp::min(lines.end, range.end);
+
+        let mut found = Vec::new();
+        let mut line = self.logical_start(cmp::max(lines.start, range.start));
+
+        while line < end {
+            let logical = self.logical(line);
+
+            found.extend(
+                logical
+                    .matches(query)
+                    .into_iter()
+                    .filter(|m| m.end.0 >= lines.start && m.start.0 < lines.end),
+            );
+
+            line = logical.end;
+        }
+
+        found
+    }
+
+    /// The first line of the run of soft-wrapped lines that `line` is in.
+    fn logical_start(&self, mut line: u64) -> u64 {
+        let start = self.line_range().start;
+
+        while line > start && self.line(line - 1).is_some_and(|l| l.is_wrapped()) {
+            line -= 1;
+        }
+
+        line
+    }
+
+    /// Join `line` and the soft-wrapped lines after it.
+    fn logical(&self, mut line: u64) -> Logical {
+        let mut text = String::new();
+        let mut positions = Vec::new();
+
+        while let Some(l) = self.line(line) {
+            for (col, c) in l.text().chars().enumerate() {
+                positions.push((text.len(), (line, Col(col as u16))));
+                text.push(c);
+            }
+
+            line += 1;
+
+            if !l.is_wrapped() {
+                break;
+            }
+        }
+
+        Logical {
+            text,
+            positions,
+            end: line,
+        }
+    }
+}
@@ -1,6 +1,10 @@
-use ndarray::Array2;
+use ndarray::{Array2, ArrayView1};
 
 use std::cmp;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::mem;
+use std::ops::Range;
 
 #[derive(
     From,
@@ -20,6 +24,7 @@ use std::cmp;
     DivAssign,
     Ord,
     PartialOrd,
+    Default,
     Serialize,
     Deserialize,
 )]
@@ -30,7 +35,7 @@ impl Row {
         if self >= state.rows() {
             None
         } else {
-            Some((self.0 as usize + state.top) % state.rows().0 as usize)
+            Some((self.0 as usize + state.grid.top) % state.rows().0 as usize)
         }
     }
 }
@@ -53,6 +58,7 @@ impl Row {
     DivAssign,
     Ord,
     PartialOrd,
+    Default,
     Serialize,
     Deserialize,
 )]
@@ -68,19 +74,14 @@ impl Col {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum CursorStyle {
+    #[default]
     Block,
     Beam,
     Underline,
 }
 
-impl Default for CursorStyle {
-    fn default() -> Self {
-        CursorStyle::Block
-    }
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Color {
     _p: (),
@@ -107,6 +108,44 @@ impl Color {
     pub fn new(r: u8, g: u8, b: u8) -> Self {
         Color { _p: (), r, g, b }
     }
+
+    /// Look up a color in the standard xterm 256 color palette.
+    pub fn indexed(idx: u8) -> Self {
+        const ANSI: [(u8, u8, u8); 16] = [
+            (0, 0, 0),
+            (205, 0, 0),
+            (0, 205, 0),
+            (205, 205, 0),
+            (0, 0, 238),
+            (205, 0, 205),
+            (0, 205, 205),
+            (229, 229, 229),
+            (127, 127, 127),
+            (255, 0, 0),
+            (0, 255, 0),
+            (255, 255, 0),
+            (92, 92, 255),
+            (255, 0, 255),
+            (0, 255, 255),
+            (255, 255, 255),
+        ];
+
+        match idx {
+            0..=15 => {
+                let (r, g, b) = ANSI[idx as usize];
+                Color::new(r, g, b)
+            }
+            16..=231 => {
+                let level = |x: u8| if x == 0 { 0 } else { 55 + 40 * x };
+                let idx = idx - 16;
+                Color::new(level(idx / 36), level((idx / 6) % 6), level(idx % 6))
+            }
+            232..=255 => {
+                let gray = 8 + 10 * (idx - 232);
+                Color::new(gray, gray, gray)
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -152,7 +191,7 @@ impl Default for CellStyle {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Cell {
     _p: (),
 
@@ -174,21 +213,658 @@ impl Default for Cell {
     }
 }
 
+/// The attributes applied to each cell as it is printed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pen {
+    _p: (),
+
+    pub style: CellStyle,
+    pub foreground: Color,
+    pub background: Color,
+}
+
+impl Default for Pen {
+    fn default() -> Self {
+        let cell = Cell::default();
+
+        Pen {
+            _p: (),
+            style: cell.style,
+            foreground: cell.foreground,
+            background: cell.background,
+        }
+    }
+}
+
+impl From<&Cell> for Pen {
+    fn from(cell: &Cell) -> Self {
+        Pen {
+            _p: (),
+            style: cell.style,
+            foreground: cell.foreground,
+            background: cell.background,
+        }
+    }
+}
+
+impl Pen {
+    /// Whether `cell` was printed with this pen.
+    fn matches(&self, cell: &Cell) -> bool {
+        self.style == cell.style
+            && self.foreground == cell.foreground
+            && self.background == cell.background
+    }
+}
+
+bitflags! {
+    /// Terminal modes that change how output is interpreted or how input
+    /// should be encoded.
+    #[derive(Serialize, Deserialize)]
+    pub struct Mode: u8 {
+        /// DECAWM - wrap to the next line after printing in the last column.
+        const AUTOWRAP          = 0b00000001;
+        /// DECOM - cursor addressing is relative to the scrolling region.
+        const ORIGIN            = 0b00000010;
+        /// DECCKM - cursor keys send application sequences.
+        const APP_CURSOR        = 0b00000100;
+        /// DECKPAM - the keypad sends application sequences.
+        const APP_KEYPAD        = 0b00001000;
+        /// IRM - printing shifts the rest of the line right.
+        const INSERT            = 0b00010000;
+        /// LNM - linefeeds also return the carriage.
+        const LINEFEED_NEWLINE  = 0b00100000;
+        /// Pasted text is wrapped in `ESC [ 200 ~` and `ESC [ 201 ~`.
+        const BRACKETED_PASTE   = 0b01000000;
+        /// Focus changes are reported with `ESC [ I` and `ESC [ O`.
+        const FOCUS_REPORT      = 0b10000000;
+    }
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::AUTOWRAP
+    }
+}
+
+/// The portion of the display or line affected by an erase operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Erase {
+    /// From the cursor to the end, inclusive.
+    ToEnd,
+    /// From the start to the cursor, inclusive.
+    ToStart,
+    /// Everything.
+    All,
+}
+
+/// Which mouse events the child wants reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MouseTracking {
+    #[default]
+    Off,
+    /// Mode 9 - only button presses.
+    X10,
+    /// Mode 1000 - presses and releases.
+    Normal,
+    /// Mode 1002 - presses, releases, and motion while a button is held.
+    Button,
+    /// Mode 1003 - presses, releases, and all motion.
+    Any,
+}
+
+/// How reported mouse events are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MouseEncoding {
+    /// `CSI M` followed by three bytes, which limits coordinates to 223.
+    #[default]
+    X10,
+    /// Mode 1005 - like `X10`, but values are UTF-8 encoded.
+    Utf8,
+    /// Mode 1006 - `CSI < b ; x ; y M`, with `m` for releases.
+    Sgr,
+    /// Mode 1015 - `CSI b ; x ; y M`.
+    Urxvt,
+}
+
+/// A character set that can be designated into one of the G0-G3 slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Charset {
+    #[default]
+    Ascii,
+    /// The DEC line drawing set.
+    DecSpecialGraphics,
+}
+
+impl Charset {
+    fn map(self, c: char) -> char {
+        match self {
+            Charset::Ascii => c,
+            Charset::DecSpecialGraphics => match c {
+                '_' => ' ',
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                c => c,
+            },
+        }
+    }
+}
+
+/// One of the four slots a character set can be designated into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CharsetSlot {
+    #[default]
+    G0,
+    G1,
+    G2,
+    G3,
+}
+
+/// The designated character sets and which slot is used for printing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Charsets {
+    slots: [Charset; 4],
+    active: CharsetSlot,
+}
+
+impl Charsets {
+    fn map(&self, c: char) -> char {
+        self.slots[self.active as usize].map(c)
+    }
+}
+
+/// The state captured by `State::save_cursor` (DECSC).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SavedCursor {
+    position: (Row, Col),
+    pen: Pen,
+    origin: bool,
+    charsets: Charsets,
+
+    /// Whether the cursor was waiting to wrap, so the next character printed
+    /// after restoring goes on the next line.
+    wrap_pending: bool,
+}
+
+/// A row of scrollback. Cells are stored as the row's text plus runs of
+/// cells sharing a pen, and trailing blank cells are dropped unless the row
+/// was soft-wrapped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Line {
+    text: Box<str>,
+    runs: Box<[Run]>,
+    wrapped: bool,
+}
+
+/// Consecutive cells in a `Line` with the same pen.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Run {
+    len: u16,
+    pen: Pen,
+
+    /// Whether the cells were printed to, rather than blank. Both are spaces
+    /// in the line's text.
+    written: bool,
+}
+
+impl Line {
+    fn new(cells: &[Cell], wrapped: bool) -> Self {
+        let mut len = cells.len();
+
+        if !wrapped {
+            let blank = Cell::default();
+            while len > 0 && cells[len - 1] == blank {
+                len -= 1;
+            }
+        }
+
+        let mut text = String::with_capacity(len);
+        let mut runs: Vec<Run> = Vec::new();
+
+        for cell in &cells[..len] {
+            text.push(cell.content.unwrap_or(' '));
+
+            let written = cell.content.is_some();
+
+            match runs.last_mut() {
+                Some(run) if run.written == written && run.pen.matches(cell) => run.len += 1,
+                _ => runs.push(Run {
+                    len: 1,
+                    pen: Pen::from(cell),
+                    written,
+                }),
+            }
+        }
+
+        Line {
+            text: text.into_boxed_str(),
+            runs: runs.into_boxed_slice(),
+            wrapped,
+        }
+    }
+
+    /// The characters in the line. Blank cells are spaces.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether the line was soft-wrapped onto the line after it.
+    pub fn is_wrapped(&self) -> bool {
+        self.wrapped
+    }
+
+    /// The number of cells stored for the line.
+    pub fn len(&self) -> usize {
+        self.runs.iter().map(|run| run.len as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// The line's cells, without any trailing blank cells.
+    pub fn cells(&self) -> impl Iterator<Item = Cell> + '_ {
+        let runs = self
+            .runs
+            .iter()
+            .flat_map(|run| std::iter::repeat_n(run, run.len as usize));
+
+        self.text.chars().zip(runs).map(|(c, run)| Cell {
+            _p: (),
+            style: run.pen.style,
+            foreground: run.pen.foreground,
+            background: run.pen.background,
+            content: Some(c).filter(|_| run.written),
+        })
+    }
+}
+
+/// The default number of lines of scrollback kept.
+pub const DEFAULT_HISTORY_LIMIT: usize = 10_000;
+
+/// Lines that have scrolled off the top of the primary screen, oldest first.
+///
+/// Lines are numbered from the first line ever scrolled off, so a line keeps
+/// its number as older lines are discarded.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct State {
+pub struct History {
+    lines: VecDeque<Line>,
+    limit: usize,
+
+    /// The number of the oldest line still stored.
+    first: u64,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            lines: VecDeque::new(),
+            limit: DEFAULT_HISTORY_LIMIT,
+            first: 0,
+        }
+    }
+}
+
+impl History {
+    /// The numbers of the lines currently stored.
+    pub fn range(&self) -> Range<u64> {
+        self.first..self.first + self.lines.len() as u64
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The most lines that will be kept.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The line numbered `line`, if it is still stored.
+    pub fn get(&self, line: u64) -> Option<&Line> {
+        let index = line.checked_sub(self.first)?;
+        self.lines.get(usize::try_from(index).ok()?)
+    }
+
+    /// The stored lines numbered within `range`, oldest first.
+    pub fn lines(&self, range: Range<u64>) -> impl Iterator<Item = &Line> {
+        let start = cmp::max(range.start, self.first) - self.first;
+        let end = cmp::max(cmp::min(range.end, self.range().end), self.first) - self.first;
+        let start = cmp::min(start, end);
+
+        self.lines.range(start as usize..end as usize)
+    }
+
+    fn push(&mut self, line: Line) {
+        self.lines.push_back(line);
+        self.trim();
+    }
+
+    fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    fn clear(&mut self) {
+        self.first += self.lines.len() as u64;
+        self.lines.clear();
+    }
+
+    fn trim(&mut self) {
+        while self.lines.len() > self.limit {
+            self.lines.pop_front();
+            self.first += 1;
+        }
+    }
+}
+
+/// A screen buffer: its cells, ring buffer offset, and saved cursor.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Grid {
     cells: Array2<Cell>,
+
+    /// Whether each row (indexed like `cells`) was soft-wrapped onto the row
+    /// below it, rather than ending in a hard line break.
+    wrapped: Vec<bool>,
+
     top: usize,
+    saved: Option<SavedCursor>,
+}
+
+impl Grid {
+    fn new(rows: Row, cols: Col) -> Self {
+        Grid {
+            cells: Array2::default((rows.0 as usize, cols.0 as usize)),
+            wrapped: vec![false; rows.0 as usize],
+            top: 0,
+            saved: None,
+        }
+    }
+
+    /// Re-wrap the grid's lines, and the end of `history`, to fit `cols`,
+    /// and resize the grid to `rows`. Lines move between the grid and
+    /// `history` as needed to keep the line under `cursor` on screen, and
+    /// `cursor` is updated to point at the same character as before. A
+    /// cursor just past the end of a full row is left in column `cols`.
+    ///
+    /// Only as much history as could be pulled back on screen is re-wrapped,
+    /// so resizing doesn't get slower as the scrollback grows. Older lines
+    /// keep the width they were written at, and their line numbers.
+    fn reflow(&mut self, rows: Row, cols: Col, cursor: &mut (Row, Col), history: &mut History) {
+        let old_rows = self.cells.dim().0;
+        let width = cols.0 as usize;
+
+        let saved = self.saved.as_ref().map(|s| s.position);
+
+        // Take whole logical lines, so none is split between a part that is
+        // re-wrapped and a part that isn't.
+        let mut take = cmp::min(history.len(), rows.0 as usize);
+        while take < history.len() && history.lines[history.len() - take - 1].wrapped {
+            take += 1;
+        }
+
+        let start = history.len() - take;
+        let scrolled: Vec<Line> = history.lines.drain(start..).collect();
+        let base = scrolled.len();
+        let total = base + old_rows;
+
+        let screen = (0..old_rows).map(|row| {
+            let physical = (row + self.top) % old_rows;
+            (self.cells.row(physical).to_vec(), self.wrapped[physical])
+        });
+
+        let all = scrolled
+            .iter()
+            .map(|line| (line.cells().collect::<Vec<_>>(), line.wrapped))
+            .chain(screen);
+
+        // Join soft-wrapped rows into logical lines, remembering each
+        // anchor as a line number and offset into that line.
+        let mut lines: Vec<Vec<Cell>> = Vec::new();
+        let mut line = Vec::new();
+        let mut cursor_at = (0, 0);
+        let mut saved_at = None;
+
+        for (row, (cells, wrapped)) in all.enumerate() {
+            if base + cursor.0 .0 as usize == row {
+                cursor_at = (lines.len(), line.len() + cursor.1 .0 as usize);
+            }
+
+            if let Some((_, c)) = saved.filter(|(r, _)| base + r.0 as usize == row) {
+                saved_at = Some((lines.len(), line.len() + c.0 as usize));
+            }
+
+            line.extend(cells);
+
+            if !wrapped || row + 1 == total {
+                let blank = Cell::default();
+                while line.last() == Some(&blank) {
+                    line.pop();
+                }
+
+                lines.push(mem::take(&mut line));
+            }
+        }
+
+        // Lay the lines out again at the new width.
+        let mut laid: Vec<(&[Cell], bool)> = Vec::new();
+        let mut new_cursor = (0, 0);
+        let mut new_saved = None;
+
+        for (index, line) in lines.iter().enumerate() {
+            let first = laid.len();
+            let count = cmp::max(1, line.len().div_ceil(width));
+
+            for chunk in 0..count {
+                let start = cmp::min(chunk * width, line.len());
+                let end = cmp::min(start + width, line.len());
+                laid.push((&line[start..end], chunk + 1 < count));
+            }
+
+            let place = |offset: usize| {
+                let row = cmp::min(offset / width, count - 1);
+                let col = match offset - row * width {
+                    col if col < width => col,
+                    _ if offset == line.len() => width,
+                    _ => width - 1,
+                };
+                (first + row, col)
+            };
+
+            if cursor_at.0 == index {
+                new_cursor = place(cursor_at.1);
+            }
+
+            if let Some((_, offset)) = saved_at.filter(|(l, _)| *l == index) {
+                let (row, col) = place(offset);
+                new_saved = Some((row, cmp::min(col, width - 1)));
+            }
+        }
+
+        // Empty rows below the cursor would only push content off the top.
+        while laid.len() > new_cursor.0 + 1 && laid.last().is_some_and(|(c, _)| c.is_empty()) {
+            laid.pop();
+        }
+
+        let height = rows.0 as usize;
+        let skip = cmp::min(laid.len().saturating_sub(height), new_cursor.0);
+
+        for (line, wrap) in &laid[..skip] {
+            history.push(Line::new(line, *wrap));
+        }
+
+        let mut cells = Array2::default((height, width));
+        let mut wrapped = vec![false; height];
+
+        for (row, (line, wrap)) in laid.iter().skip(skip).take(height).enumerate() {
+            cells
+                .slice_mut(s![row, ..line.len()])
+                .assign(&ArrayView1::from(*line));
+            wrapped[row] = *wrap;
+        }
+
+        let shift =
+            |(row, col): (usize, usize)| (Row(row.saturating_sub(skip) as u16), Col(col as u16));
+
+        *cursor = shift(new_cursor);
+
+        if let (Some(slot), Some(at)) = (self.saved.as_mut(), new_saved) {
+            slot.position = shift(at);
+        }
+
+        self.cells = cells;
+        self.wrapped = wrapped;
+        self.top = 0;
+    }
+
+    /// Resize to `rows` by `cols`, keeping the line at `anchor` on screen by
+    /// dropping lines from the top if the grid gets too short. Returns the
+    /// number of lines dropped.
+    fn resize(&mut self, rows: Row, cols: Col, anchor: Row) -> u16 {
+        let (old_rows, old_cols) = self.cells.dim();
+        let dropped = cmp::min((anchor.0 + 1).saturating_sub(rows.0), old_rows as u16);
+
+        let keep_rows = cmp::min(old_rows - dropped as usize, rows.0 as usize);
+        let keep_cols = cmp::min(old_cols, cols.0 as usize);
+
+        let mut cells = Array2::default((rows.0 as usize, cols.0 as usize));
+        let mut wrapped = vec![false; rows.0 as usize];
+
+        for (row, wrap) in wrapped.iter_mut().enumerate().take(keep_rows) {
+            let src = (row + dropped as usize + self.top) % old_rows;
+            cells
+                .slice_mut(s![row, ..keep_cols])
+                .assign(&self.cells.slice(s![src, ..keep_cols]));
+            *wrap = self.wrapped[src];
+        }
+
+        self.cells = cells;
+        self.wrapped = wrapped;
+        self.top = 0;
+
+        if let Some(saved) = self.saved.as_mut() {
+            saved.position.0 = Row(saved.position.0 .0.saturating_sub(dropped));
+        }
+
+        dropped
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct State {
+    /// The screen buffer currently being displayed.
+    grid: Grid,
+
+    /// The screen buffer not currently being displayed. Clients only ever
+    /// render the active buffer, so this isn't sent to them.
+    #[serde(skip)]
+    inactive: Grid,
+
+    alternate: bool,
+
+    /// Lines scrolled off the top of the primary screen. This can be large,
+    /// so clients fetch it separately instead of with every state.
+    #[serde(skip)]
+    history: History,
+
+    /// The scrolling region, as `(top, bottom)` with `bottom` exclusive.
+    margins: (Row, Row),
+
+    modes: Mode,
+
+    /// How many modified keys are sent as `CSI 27 ; m ; code ~`, as set by
+    /// xterm's `CSI > 4 ; level m`.
+    modify_other_keys: u8,
+
+    mouse_tracking: MouseTracking,
+    mouse_encoding: MouseEncoding,
+
+    charsets: Charsets,
+
+    /// Whether each column has a tab stop.
+    tabs: Vec<bool>,
+
+    /// Incremented every time the bell rings, so clients can tell when it
+    /// has rung since the last state they saw.
+    bells: u32,
+
+    /// Set after printing in the last column with autowrap enabled. The
+    /// cursor stays put until the next character is printed, which wraps.
+    wrap_pending: bool,
+
+    /// The window title, set with OSC 0 or 2.
+    title: String,
+
+    /// The icon name, set with OSC 0 or 1.
+    icon_name: String,
+
+    /// The directory the child says it's in, from the path of the last
+    /// `file://` URL sent with OSC 7.
+    working_directory: Option<String>,
+
+    /// The colors SGR indexed colors refer to, as changed with OSC 4. Cells
+    /// are stored with their colors resolved, so clients don't need this.
+    #[serde(skip, default = "default_palette")]
+    palette: Vec<Color>,
+
+    /// The colors SGR 0, 39 and 49 return to, as changed with OSC 10 and 11.
+    default_colors: (Color, Color),
 
     pub cursor: Cursor,
+    pub pen: Pen,
+}
+
+/// The distance between the tab stops a new screen starts with.
+pub const TAB_WIDTH: u16 = 8;
+
+fn default_tabs(cols: Col) -> Vec<bool> {
+    (0..cols.0).map(|c| c % TAB_WIDTH == 0).collect()
+}
+
+fn default_palette() -> Vec<Color> {
+    (0..=255).map(Color::indexed).collect()
+}
+
+fn default_colors() -> (Color, Color) {
+    let cell = Cell::default();
+    (cell.foreground, cell.background)
 }
 
 impl Default for State {
     fn default() -> Self {
-        State {
-            cursor: Cursor::default(),
-            top: 0,
-            cells: Array2::default((24, 80)),
-        }
+        State::with_dimensions(Row(24), Col(80))
     }
 }
 
@@ -196,76 +872,743 @@ impl State {
     pub fn with_dimensions(rows: Row, cols: Col) -> Self {
         Self {
             cursor: Cursor::default(),
-            top: 0,
-            cells: Array2::default((rows.0 as usize, cols.0 as usize)),
+            pen: Pen::default(),
+            grid: Grid::new(rows, cols),
+            inactive: Grid::new(rows, cols),
+            alternate: false,
+            history: History::default(),
+            margins: (Row(0), rows),
+            modes: Mode::default(),
+            modify_other_keys: 0,
+            mouse_tracking: MouseTracking::Off,
+            mouse_encoding: MouseEncoding::X10,
+            charsets: Charsets::default(),
+            tabs: default_tabs(cols),
+            bells: 0,
+            wrap_pending: false,
+            title: String::new(),
+            icon_name: String::new(),
+            working_directory: None,
+            palette: default_palette(),
+            default_colors: default_colors(),
         }
     }
 
     pub fn cell(&self, row: Row, col: Col) -> Option<&Cell> {
-        self.cells.get([row.realize(self)?, col.realize(self)?])
+        self.grid
+            .cells
+            .get([row.realize(self)?, col.realize(self)?])
     }
 
     pub fn cell_mut(&mut self, row: Row, col: Col) -> Option<&mut Cell> {
         let rcol = col.realize(self)?;
         let rrow = row.realize(self)?;
 
-        self.cells.get_mut([rrow, rcol])
+        self.grid.cells.get_mut([rrow, rcol])
     }
 
     pub fn rows(&self) -> Row {
-        Row::from(self.cells.dim().0 as u16)
+        Row::from(self.grid.cells.dim().0 as u16)
     }
 
     pub fn columns(&self) -> Col {
-        Col::from(self.cells.dim().1 as u16)
+        Col::from(self.grid.cells.dim().1 as u16)
     }
 
-    pub fn scroll_down(&mut self, rows: Row) {
+    /// Change the size of both screen buffers, keeping as much of their
+    /// content as fits. Lines are dropped from the top when shrinking only if
+    /// needed to keep the cursor on screen. Resets the scrolling region.
+    pub fn resize(&mut self, rows: Row, cols: Col) {
+        let rows = cmp::max(rows, Row(1));
+        let cols = cmp::max(cols, Col(1));
+
+        if (rows, cols) == (self.rows(), self.columns()) {
+            return;
+        }
+
+        // Only the primary screen reflows, moving lines to and from the
+        // scrollback; programs using the alternate screen redraw it
+        // themselves.
+        let mut cursor = self.cursor.position;
+        let wrap_pending;
+
+        if self.alternate {
+            let old_cols = self.columns();
+            let dropped = self.grid.resize(rows, cols, cursor.0);
+            cursor.0 = Row(cursor.0 .0.saturating_sub(dropped));
+            wrap_pending = self.wrap_pending && cols == old_cols;
+
+            let mut anchor = match self.inactive.saved {
+                Some(ref saved) => saved.position,
+                None => self.cursor.position,
+            };
+
+            self.inactive
+                .reflow(rows, cols, &mut anchor, &mut self.history);
+        } else {
+            if self.wrap_pending {
+                cursor.1 += Col(1);
+            }
+
+            self.grid.reflow(rows, cols, &mut cursor, &mut self.history);
+            wrap_pending = cursor.1 == cols && self.modes.contains(Mode::AUTOWRAP);
+
+            let anchor = self
+                .inactive
+                .saved
+                .as_ref()
+                .map_or(cursor.0, |s| s.position.0);
+            self.inactive.resize(rows, cols, anchor);
+        }
+
+        let old_cols = self.tabs.len();
+        let defaults = default_tabs(cols);
+        self.tabs.resize(cols.0 as usize, false);
+        if cols.0 as usize > old_cols {
+            self.tabs[old_cols..].copy_from_slice(&defaults[old_cols..]);
+        }
+
+        self.margins = (Row(0), rows);
+
+        self.set_row(cursor.0);
+        self.set_col(cursor.1);
+        self.wrap_pending = wrap_pending;
+    }
+
+    /// Whether `row` was soft-wrapped onto the row below it, rather than
+    /// ending in a hard line break.
+    pub fn is_wrapped(&self, row: Row) -> bool {
+        row.realize(self).is_some_and(|r| self.grid.wrapped[r])
+    }
+
+    /// Lines scrolled off the top of the primary screen.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Change the most lines of scrollback kept, discarding the oldest lines
+    /// if there are now too many.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.set_limit(limit);
+    }
+
+    /// Discard all scrollback (ED 3).
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Replace the scrollback, for example with one fetched from the server.
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+    }
+
+    /// The number of the line shown on the screen's first row. The lines
+    /// before it are in the scrollback.
+    pub fn screen_start(&self) -> u64 {
+        self.history.range().end
+    }
+
+    /// The numbers of every line in the scrollback and on the screen.
+    pub fn line_range(&self) -> Range<u64> {
+        self.history.range().start..self.screen_start() + u64::from(self.rows().0)
+    }
+
+    /// The line numbered `line`, from either the scrollback or the screen.
+    pub fn line(&self, line: u64) -> Option<Line> {
+        match line.checked_sub(self.screen_start()) {
+            None => self.history.get(line).cloned(),
+            Some(row) => {
+                let row = Row(u16::try_from(row).ok()?);
+                let rrow = row.realize(self)?;
+                let cells = self.grid.cells.row(rrow);
+
+                Some(Line::new(&cells.to_vec(), self.grid.wrapped[rrow]))
+            }
+        }
+    }
+
+    /// Whether the alternate screen buffer is being displayed.
+    pub fn is_alternate_screen(&self) -> bool {
+        self.alternate
+    }
+
+    /// Switch between the primary and alternate screen buffers. The contents
+    /// of each buffer are kept while it is inactive.
+    pub fn set_alternate_screen(&mut self, alternate: bool) {
+        if self.alternate != alternate {
+            mem::swap(&mut self.grid, &mut self.inactive);
+            self.alternate = alternate;
+        }
+    }
+
+    /// Save the cursor position, pen, origin mode and character sets into
+    /// the active screen buffer's slot.
+    pub fn save_cursor(&mut self) {
+        self.grid.saved = Some(SavedCursor {
+            position: self.cursor.position,
+            pen: self.pen.clone(),
+            origin: self.modes.contains(Mode::ORIGIN),
+            charsets: self.charsets.clone(),
+            wrap_pending: self.wrap_pending,
+        });
+    }
+
+    /// Restore everything saved by `save_cursor` from the active screen
+    /// buffer's slot, or reset it if nothing was saved.
+    pub fn restore_cursor(&mut self) {
+        let saved = self.grid.saved.clone().unwrap_or_else(|| SavedCursor {
+            pen: self.default_pen(),
+            ..SavedCursor::default()
+        });
+        let (row, col) = saved.position;
+
+        self.pen = saved.pen;
+        self.modes.set(Mode::ORIGIN, saved.origin);
+        self.charsets = saved.charsets;
+        self.set_row(row);
+        self.set_col(col);
+
+        // A resize since saving can leave the cursor short of the last column.
+        self.wrap_pending = saved.wrap_pending && col == self.columns() - Col(1);
+    }
+
+    /// Designate `charset` into `slot` (SCS).
+    pub fn designate_charset(&mut self, slot: CharsetSlot, charset: Charset) {
+        self.charsets.slots[slot as usize] = charset;
+    }
+
+    /// Print using the character set designated into `slot`.
+    pub fn invoke_charset(&mut self, slot: CharsetSlot) {
+        self.charsets.active = slot;
+    }
+
+    pub fn modes(&self) -> Mode {
+        self.modes
+    }
+
+    pub fn set_mode(&mut self, mode: Mode, enable: bool) {
+        self.modes.set(mode, enable);
+
+        if mode.contains(Mode::ORIGIN) {
+            self.goto(Row(0), Col(0));
+        }
+
+        if mode.contains(Mode::AUTOWRAP) {
+            self.wrap_pending = false;
+        }
+    }
+
+    /// 0 if only the usual control characters and escape prefixes are sent
+    /// for modified keys, 1 if keys those can't express are sent in xterm's
+    /// modifyOtherKeys form, and 2 if all modified keys are.
+    pub fn modify_other_keys(&self) -> u8 {
+        self.modify_other_keys
+    }
+
+    pub fn set_modify_other_keys(&mut self, level: u8) {
+        self.modify_other_keys = cmp::min(level, 2);
+    }
+
+    pub fn mouse_tracking(&self) -> MouseTracking {
+        self.mouse_tracking
+    }
+
+    /// Turn mouse tracking on, or off if `enable` is false and it's the mode
+    /// currently in use.
+    pub fn set_mouse_tracking(&mut self, tracking: MouseTracking, enable: bool) {
+        if enable {
+            self.mouse_tracking = tracking;
+        } else if self.mouse_tracking == tracking {
+            self.mouse_tracking = MouseTracking::Off;
+        }
+    }
+
+    pub fn mouse_encoding(&self) -> MouseEncoding {
+        self.mouse_encoding
+    }
+
+    /// Switch mouse encoding, or back to the default if `enable` is false and
+    /// it's the encoding currently in use.
+    pub fn set_mouse_encoding(&mut self, encoding: MouseEncoding, enable: bool) {
+        if enable {
+            self.mouse_encoding = encoding;
+        } else if self.mouse_encoding == encoding {
+            self.mouse_encoding = MouseEncoding::X10;
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn set_title(&mut self, title: String) {
+        self.title = title;
+    }
+
+    pub fn icon_name(&self) -> &str {
+        &self.icon_name
+    }
+
+    pub fn set_icon_name(&mut self, icon_name: String) {
+        self.icon_name = icon_name;
+    }
+
+    pub fn working_directory(&self) -> Option<&str> {
+        self.working_directory.as_deref()
+    }
+
+    pub fn set_working_directory(&mut self, path: Option<String>) {
+        self.working_directory = path;
+    }
+
+    /// The color SGR uses for palette entry `idx`.
+    pub fn palette(&self, idx: u8) -> Color {
+        self.palette[usize::from(idx)]
+    }
+
+    /// Change palette entry `idx`, or restore its default if `color` is
+    /// `None`. Cells already printed keep the color they were printed with.
+    pub fn set_palette(&mut self, idx: u8, color: Option<Color>) {
+        self.palette[usize::from(idx)] = color.unwrap_or_else(|| Color::indexed(idx));
+    }
+
+    pub fn reset_palette(&mut self) {
+        self.palette = default_palette();
+    }
+
+    /// The foreground color text is printed in after SGR 0 or 39.
+    pub fn default_foreground(&self) -> Color {
+        self.default_colors.0
+    }
+
+    /// Change the default foreground, or restore it if `color` is `None`.
+    /// The pen only picks up the change the next time it is reset.
+    pub fn set_default_foreground(&mut self, color: Option<Color>) {
+        self.default_colors.0 = color.unwrap_or_else(|| default_colors().0);
+    }
+
+    /// The background color text is printed on after SGR 0 or 49.
+    pub fn default_background(&self) -> Color {
+        self.default_colors.1
+    }
+
+    /// Change the default background, or restore it if `color` is `None`.
+    /// The pen only picks up the change the next time it is reset.
+    pub fn set_default_background(&mut self, color: Option<Color>) {
+        self.default_colors.1 = color.unwrap_or_else(|| default_colors().1);
+    }
+
+    /// A pen with no style, in the default colors.
+    pub fn default_pen(&self) -> Pen {
+        Pen {
+            foreground: self.default_foreground(),
+            background: self.default_background(),
+            ..Pen::default()
+        }
+    }
+
+    /// The scrolling region, as `(top, bottom)` with `bottom` exclusive.
+    pub fn scroll_region(&self) -> (Row, Row) {
+        self.margins
+    }
+
+    /// Set the scrolling region to `top..bottom`. Regions smaller than two
+    /// lines are ignored.
+    pub fn set_scroll_region(&mut self, top: Row, bottom: Row) {
+        let bottom = cmp::min(bottom, self.rows());
+
+        if top.0 + 1 >= bottom.0 {
+            return;
+        }
+
+        self.margins = (top, bottom);
+        self.goto(Row(0), Col(0));
+    }
+
+    /// Scroll the contents of the scrolling region up by `rows`, adding blank
+    /// lines at the bottom (SU).
+    pub fn scroll_up(&mut self, rows: Row) {
+        let (top, bottom) = self.margins;
+
+        if (top, bottom) != (Row(0), self.rows()) {
+            self.shift_up(top, bottom, rows);
+            return;
+        }
+
+        // The region covers the whole screen, so rotate instead of copying.
         let to_clear = cmp::min(rows, self.rows());
+        let blank = self.blank();
 
         for _ in 0..to_clear.0 {
-            self.cells.row_mut(self.top).fill(Cell::default());
-            self.top += 1;
+            if !self.alternate {
+                let top = self.grid.top;
+                let cells = self.grid.cells.row(top);
+                let line = Line::new(cells.as_slice().unwrap(), self.grid.wrapped[top]);
+                self.history.push(line);
+            }
+
+            self.grid.cells.row_mut(self.grid.top).fill(blank.clone());
+            self.grid.wrapped[self.grid.top] = false;
+            self.grid.top = (self.grid.top + 1) % self.rows().0 as usize;
+        }
+    }
+
+    /// Scroll the contents of the scrolling region down by `rows`, adding
+    /// blank lines at the top (SD).
+    pub fn scroll_down(&mut self, rows: Row) {
+        let (top, bottom) = self.margins;
+        self.shift_down(top, bottom, rows);
+    }
+
+    /// An empty cell, colored with the current background (BCE).
+    fn blank(&self) -> Cell {
+        Cell {
+            background: self.pen.background,
+            ..Cell::default()
         }
     }
 
+    fn erase_cells(&mut self, row: Row, start: Col, end: Col) {
+        let rrow = match row.realize(self) {
+            Some(r) => r,
+            None => return,
+        };
+
+        let start = cmp::min(start, self.columns()).0 as usize;
+        let end = cmp::min(end, self.columns()).0 as usize;
+
+        if start >= end {
+            return;
+        }
+
+        let blank = self.blank();
+        self.grid.cells.slice_mut(s![rrow, start..end]).fill(blank);
+
+        // Erasing the end of a row breaks it from the row below.
+        if end == self.columns().0 as usize {
+            self.grid.wrapped[rrow] = false;
+        }
+    }
+
+    fn swap_rows(&mut self, a: Row, b: Row) {
+        let (ra, rb) = match (a.realize(self), b.realize(self)) {
+            (Some(ra), Some(rb)) => (ra, rb),
+            _ => return,
+        };
+
+        for col in 0..self.columns().0 as usize {
+            self.grid.cells.swap([ra, col], [rb, col]);
+        }
+
+        self.grid.wrapped.swap(ra, rb);
+    }
+
+    /// Move the rows in `top..bottom` up by `count`, discarding the rows that
+    /// pass `top` and blanking the rows uncovered at the bottom.
+    fn shift_up(&mut self, top: Row, bottom: Row, count: Row) {
+        let count = cmp::min(count.0, bottom.0.saturating_sub(top.0));
+
+        for row in top.0..bottom.0 - count {
+            self.swap_rows(Row(row), Row(row + count));
+        }
+
+        for row in bottom.0 - count..bottom.0 {
+            self.erase_cells(Row(row), Col(0), self.columns());
+        }
+    }
+
+    /// Move the rows in `top..bottom` down by `count`, discarding the rows
+    /// that pass `bottom` and blanking the rows uncovered at the top.
+    fn shift_down(&mut self, top: Row, bottom: Row, count: Row) {
+        let count = cmp::min(count.0, bottom.0.saturating_sub(top.0));
+
+        for row in (top.0 + count..bottom.0).rev() {
+            self.swap_rows(Row(row), Row(row - count));
+        }
+
+        for row in top.0..top.0 + count {
+            self.erase_cells(Row(row), Col(0), self.columns());
+        }
+    }
+
+    /// Insert `count` blank lines at the cursor, pushing the lines below it
+    /// down.
+    pub fn insert_lines(&mut self, count: Row) {
+        let row = self.cursor.position.0;
+        let (top, bottom) = self.margins;
+
+        if row < top || row >= bottom {
+            return;
+        }
+
+        self.shift_down(row, bottom, count);
+        self.carriage_return();
+    }
+
+    /// Delete `count` lines at the cursor, pulling the lines below it up.
+    pub fn delete_lines(&mut self, count: Row) {
+        let row = self.cursor.position.0;
+        let (top, bottom) = self.margins;
+
+        if row < top || row >= bottom {
+            return;
+        }
+
+        self.shift_up(row, bottom, count);
+        self.carriage_return();
+    }
+
+    /// Insert `count` blank cells at the cursor, pushing the rest of the line
+    /// right. Cells pushed past the last column are lost.
+    pub fn insert_chars(&mut self, count: Col) {
+        let (row, col) = self.cursor.position;
+        let rrow = match row.realize(self) {
+            Some(r) => r,
+            None => return,
+        };
+
+        let cols = self.columns().0;
+        let count = cmp::min(count.0, cols - col.0);
+
+        for c in (col.0 + count..cols).rev() {
+            let c = c as usize;
+            self.grid.cells.swap([rrow, c], [rrow, c - count as usize]);
+        }
+
+        self.erase_cells(row, col, col + Col(count));
+    }
+
+    /// Delete `count` cells at the cursor, pulling the rest of the line left.
+    pub fn delete_chars(&mut self, count: Col) {
+        let (row, col) = self.cursor.position;
+        let rrow = match row.realize(self) {
+            Some(r) => r,
+            None => return,
+        };
+
+        let cols = self.columns().0;
+        let count = cmp::min(count.0, cols - col.0);
+
+        for c in col.0..cols - count {
+            let c = c as usize;
+            self.grid.cells.swap([rrow, c], [rrow, c + count as usize]);
+        }
+
+        self.erase_cells(row, Col(cols - count), Col(cols));
+    }
+
+    pub fn erase_display(&mut self, erase: Erase) {
+        let row = self.cursor.position.0;
+
+        let rows = match erase {
+            Erase::ToEnd => row.0 + 1..self.rows().0,
+            Erase::ToStart => 0..row.0,
+            Erase::All => 0..self.rows().0,
+        };
+
+        if erase != Erase::All {
+            self.erase_line(erase);
+        }
+
+        for row in rows {
+            self.erase_cells(Row(row), Col(0), self.columns());
+        }
+    }
+
+    pub fn erase_line(&mut self, erase: Erase) {
+        let (row, col) = self.cursor.position;
+
+        match erase {
+            Erase::ToEnd => self.erase_cells(row, col, self.columns()),
+            Erase::ToStart => self.erase_cells(row, Col(0), col + Col(1)),
+            Erase::All => self.erase_cells(row, Col(0), self.columns()),
+        }
+    }
+
+    /// Erase `count` cells starting at the cursor, without moving anything.
+    pub fn erase_chars(&mut self, count: Col) {
+        let (row, col) = self.cursor.position;
+        let end = Col(col.0.saturating_add(count.0));
+        self.erase_cells(row, col, end);
+    }
+
+    /// Move the cursor to `row` and `col`, relative to the scrolling region
+    /// in origin mode.
+    pub fn goto(&mut self, row: Row, col: Col) {
+        self.goto_row(row);
+        self.goto_col(col);
+    }
+
+    /// Move the cursor to `row`, relative to the scrolling region in origin
+    /// mode.
     pub fn goto_row(&mut self, row: Row) {
-        self.cursor.position.0 = row;
+        if self.modes.contains(Mode::ORIGIN) {
+            let (top, bottom) = self.margins;
+            let row = Row(row.0.saturating_add(top.0));
+            self.set_row(cmp::min(row, bottom - Row(1)));
+        } else {
+            self.set_row(row);
+        }
     }
 
     pub fn goto_col(&mut self, col: Col) {
-        self.cursor.position.1 = col;
+        self.set_col(col);
+    }
+
+    fn set_row(&mut self, row: Row) {
+        self.wrap_pending = false;
+        self.cursor.position.0 = cmp::min(row, self.rows() - Row(1));
+    }
+
+    fn set_col(&mut self, col: Col) {
+        self.wrap_pending = false;
+        self.cursor.position.1 = cmp::min(col, self.columns() - Col(1));
+    }
+
+    /// Move the cursor up, stopping at the top margin if the cursor starts
+    /// inside the scrolling region.
+    pub fn move_up(&mut self, rows: Row) {
+        let row = self.cursor.position.0;
+        let top = self.margins.0;
+        let limit = if row >= top { top } else { Row(0) };
+
+        self.set_row(cmp::max(limit, Row(row.0.saturating_sub(rows.0))));
+    }
+
+    /// Move the cursor down, stopping at the bottom margin if the cursor
+    /// starts inside the scrolling region.
+    pub fn move_down(&mut self, rows: Row) {
+        let row = self.cursor.position.0;
+        let bottom = self.margins.1;
+        let limit = if row < bottom { bottom } else { self.rows() };
+
+        self.set_row(cmp::min(limit - Row(1), Row(row.0.saturating_add(rows.0))));
+    }
+
+    pub fn move_forward(&mut self, cols: Col) {
+        let col = self.cursor.position.1;
+        self.set_col(Col(col.0.saturating_add(cols.0)));
+    }
+
+    pub fn move_backward(&mut self, cols: Col) {
+        let col = self.cursor.position.1;
+        self.set_col(Col(col.0.saturating_sub(cols.0)));
+    }
+
+    pub fn ring_bell(&mut self) {
+        self.bells = self.bells.wrapping_add(1);
+    }
+
+    /// The number of times the bell has rung, wrapping on overflow.
+    pub fn bell_count(&self) -> u32 {
+        self.bells
+    }
+
+    /// Move the cursor forward `count` tab stops, or to the last column if
+    /// there are no more (HT, CHT).
+    pub fn tab(&mut self, count: u16) {
+        let mut col = self.cursor.position.1 .0 as usize;
+        let last = self.tabs.len() - 1;
+
+        for _ in 0..count {
+            col = (col + 1..last).find(|&c| self.tabs[c]).unwrap_or(last);
+        }
+
+        self.set_col(Col(col as u16));
+    }
+
+    /// Move the cursor back `count` tab stops, or to the first column if
+    /// there are no more (CBT).
+    pub fn back_tab(&mut self, count: u16) {
+        let mut col = self.cursor.position.1 .0 as usize;
+
+        for _ in 0..count {
+            col = (0..col).rev().find(|&c| self.tabs[c]).unwrap_or(0);
+        }
+
+        self.set_col(Col(col as u16));
+    }
+
+    /// Set a tab stop at the cursor's column (HTS).
+    pub fn set_tab_stop(&mut self) {
+        let col = self.cursor.position.1 .0 as usize;
+        self.tabs[col] = true;
+    }
+
+    /// Clear the tab stop at the cursor's column (TBC 0).
+    pub fn clear_tab_stop(&mut self) {
+        let col = self.cursor.position.1 .0 as usize;
+        self.tabs[col] = false;
+    }
+
+    /// Clear every tab stop (TBC 3).
+    pub fn clear_all_tab_stops(&mut self) {
+        self.tabs.iter_mut().for_each(|t| *t = false);
     }
 
     pub fn print(&mut self, c: char) {
+        let c = self.charsets.map(c);
+
+        if self.wrap_pending {
+            if let Some(rrow) = self.cursor.position.0.realize(self) {
+                self.grid.wrapped[rrow] = true;
+            }
+
+            self.carriage_return();
+            self.linefeed();
+        }
+
+        if self.modes.contains(Mode::INSERT) {
+            self.insert_chars(Col(1));
+        }
+
         let (row, col) = self.cursor.position;
 
         {
+            let pen = self.pen.clone();
             let cell = self.cell_mut(row, col);
 
             if let Some(cell) = cell {
+                cell.style = pen.style;
+                cell.foreground = pen.foreground;
+                cell.background = pen.background;
                 cell.content = Some(c);
             }
         }
 
-        if col >= self.columns() - Col(1) {
-            self.cursor.position.0 += Row(1);
-            self.cursor.position.1 = Col(0);
-
-            if self.cursor.position.0 >= self.rows() {
-                self.cursor.position.0 = self.rows() - Row(1);
-                self.scroll_down(Row(1));
-            }
-        } else {
+        if col < self.columns() - Col(1) {
             self.cursor.position.1 += Col(1);
+        } else if self.modes.contains(Mode::AUTOWRAP) {
+            self.wrap_pending = true;
         }
     }
 
     pub fn carriage_return(&mut self) {
-        self.cursor.position.1 = Col(0);
+        self.set_col(Col(0));
     }
 
+    /// Move the cursor down one line, scrolling if it is on the bottom margin
+    /// (IND).
     pub fn linefeed(&mut self) {
-        self.cursor.position.0 += Row(1);
+        let row = self.cursor.position.0;
+
+        if row == self.margins.1 - Row(1) {
+            self.wrap_pending = false;
+            self.scroll_up(Row(1));
+        } else {
+            self.set_row(row + Row(1));
+        }
+    }
+
+    /// Move the cursor up one line, scrolling if it is on the top margin (RI).
+    pub fn reverse_index(&mut self) {
+        let row = self.cursor.position.0;
+
+        if row == self.margins.0 {
+            self.wrap_pending = false;
+            self.scroll_down(Row(1));
+        } else {
+            self.set_row(Row(row.0.saturating_sub(1)));
+        }
     }
 }
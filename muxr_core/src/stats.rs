@@ -0,0 +1,19 @@
+/// Counts of the control functions a server has received but not handled.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    pub unhandled_execute: u64,
+    pub unhandled_csi: u64,
+    pub unhandled_esc: u64,
+    pub unhandled_osc: u64,
+    pub unhandled_dcs: u64,
+}
+
+impl Stats {
+    pub fn unhandled(&self) -> u64 {
+        self.unhandled_execute
+            + self.unhandled_csi
+            + self.unhandled_esc
+            + self.unhandled_osc
+            + self.unhandled_dcs
+    }
+}
@@ -1,7 +1,7 @@
-extern crate muxr;
+extern crate muxr_core;
 
 mod row {
-    use muxr::state::Row;
+    use muxr_core::state::Row;
 
     #[test]
     fn div() {
@@ -19,7 +19,7 @@ mod row {
 }
 
 mod col {
-    use muxr::state::Col;
+    use muxr_core::state::Col;
 
     #[test]
     fn div() {
@@ -0,0 +1,90 @@
+extern crate muxr_core;
+
+use muxr_core::search::{Direction, Match, Query};
+use muxr_core::state::{Col, Row, State};
+
+fn state(rows: u16, cols: u16, text: &str) -> State {
+    let mut state = State::with_dimensions(Row(rows), Col(cols));
+
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            state.carriage_return();
+            state.linefeed();
+        }
+
+        line.chars().for_each(|c| state.print(c));
+    }
+
+    state
+}
+
+fn at(start: (u64, u16), end: (u64, u16)) -> Match {
+    Match {
+        start: (start.0, Col(start.1)),
+        end: (end.0, Col(end.1)),
+    }
+}
+
+#[test]
+fn plain_forward_and_backward() {
+    let state = state(3, 10, "foo bar\nbar foo\nbaz");
+    let query = Query::new("foo", false, false).unwrap();
+
+    let found = state.search(&query, (0, Col(0)), Direction::Forward, false);
+    assert_eq!(found, Some(at((1, 4), (1, 6))));
+
+    let found = state.search(&query, (1, Col(4)), Direction::Backward, false);
+    assert_eq!(found, Some(at((0, 0), (0, 2))));
+
+    let found = state.search(&query, (1, Col(4)), Direction::Forward, false);
+    assert_eq!(found, None);
+}
+
+#[test]
+fn wraps_around() {
+    let state = state(3, 10, "foo\nbar\nbaz");
+    let query = Query::new("foo", false, false).unwrap();
+
+    let found = state.search(&query, (1, Col(0)), Direction::Forward, false);
+    assert_eq!(found, None);
+
+    let found = state.search(&query, (1, Col(0)), Direction::Forward, true);
+    assert_eq!(found, Some(at((0, 0), (0, 2))));
+
+    // The only match is where the search starts from.
+    let found = state.search(&query, (0, Col(0)), Direction::Backward, true);
+    assert_eq!(found, Some(at((0, 0), (0, 2))));
+}
+
+#[test]
+fn regex_and_case() {
+    let state = state(2, 10, "Foo f00\nfOO");
+
+    let query = Query::new("foo", false, true).unwrap();
+    let found = state.search(&query, (0, Col(0)), Direction::Forward, false);
+    assert_eq!(found, Some(at((1, 0), (1, 2))));
+
+    let query = Query::new("f[0o]+", true, false).unwrap();
+    let found = state.search(&query, (0, Col(0)), Direction::Forward, false);
+    assert_eq!(found, Some(at((0, 4), (0, 6))));
+
+    // Plain queries don't treat anything as special.
+    let query = Query::new("f.", false, false).unwrap();
+    assert_eq!(state.matches(&query, 0..2), Vec::new());
+
+    assert!(Query::new("(", true, false).is_err());
+}
+
+#[test]
+fn matches_across_wrapped_lines_and_history() {
+    let state = state(2, 4, "abcdef\nx\ny");
+    assert_eq!(state.history().len(), 2);
+
+    let query = Query::new("cde", false, false).unwrap();
+    let found = state.search(&query, (3, Col(0)), Direction::Backward, false);
+    assert_eq!(found, Some(at((0, 2), (1, 0))));
+
+    // Matches are found for a range that only covers part of them.
+    assert_eq!(state.matches(&query, 1..2), vec![at((0, 2), (1, 0))]);
+    assert_eq!(state.matches(&query, 2..4), Vec::new());
+}
@@ -1,5 +1,6 @@
 extern crate cc;
 
 fn main() {
+    println!("cargo:rerun-if-changed=src/pty.c");
     cc::Build::new().file("src/pty.c").compile("pty_helper");
 }
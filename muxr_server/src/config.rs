@@ -1,5 +1,9 @@
+use crate::error::*;
+
+use std::env;
 use std::path::PathBuf;
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct Config {
     server: Server,
@@ -8,4 +12,37 @@ pub struct Config {
 #[derive(Debug)]
 pub struct Server {
     pub socket_path: PathBuf,
+
+    /// The most lines of scrollback to keep.
+    pub history_limit: usize,
+
+    /// Whether text the child copies with OSC 52 is also sent to clients, to
+    /// put on the clipboard of the terminal they're running in.
+    pub forward_clipboard: bool,
+
+    /// How to answer the child asking for the clipboard with OSC 52.
+    pub clipboard_read: ClipboardRead,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardRead {
+    /// Ignore the request, since anything running in the terminal could read
+    /// whatever was copied.
+    Deny,
+    /// Answer with the most recent paste buffer.
+    Buffer,
+}
+
+impl ClipboardRead {
+    /// Read the policy from `$MUXR_CLIPBOARD_READ`, which can be `deny` or
+    /// `buffer`, denying if it isn't set.
+    pub fn from_env() -> Result<Self> {
+        match env::var("MUXR_CLIPBOARD_READ") {
+            Err(env::VarError::NotPresent) => Ok(ClipboardRead::Deny),
+            Ok(ref value) if value == "deny" => Ok(ClipboardRead::Deny),
+            Ok(ref value) if value == "buffer" => Ok(ClipboardRead::Buffer),
+            Ok(value) => bail!("unknown MUXR_CLIPBOARD_READ policy: {}", value),
+            Err(e) => Err(e).chain_err(|| "invalid MUXR_CLIPBOARD_READ"),
+        }
+    }
 }
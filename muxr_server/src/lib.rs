@@ -2,6 +2,8 @@
 extern crate error_chain;
 #[macro_use]
 extern crate lazy_static;
+#[macro_use]
+extern crate log;
 
 mod config;
 mod error;
@@ -15,7 +17,8 @@ use crate::pty::CommandTty;
 use futures_util::future::{self, TryFutureExt};
 use futures_util::pin_mut;
 
-use muxr_core::state::State;
+use muxr_core::state::{State, DEFAULT_HISTORY_LIMIT};
+use muxr_core::stats::Stats;
 
 use std::fs::File;
 use std::path::PathBuf;
@@ -35,6 +38,8 @@ pub fn run() -> Result<()> {
         .start()
         .chain_err(|| "unable to daemonize")?;
 
+    env_logger::init();
+
     async_run()
 }
 
@@ -42,13 +47,27 @@ pub fn run() -> Result<()> {
 async fn async_run() -> Result<()> {
     let config = config::Server {
         socket_path: PathBuf::from("/tmp/muxr.sock"),
+        history_limit: DEFAULT_HISTORY_LIMIT,
+        forward_clipboard: true,
+        clipboard_read: config::ClipboardRead::from_env()?,
     };
 
-    let state = Arc::new(Mutex::new(State::default()));
+    let mut state = State::default();
+    state.set_history_limit(config.history_limit);
+
+    let state = Arc::new(Mutex::new(state));
+    let stats = Arc::new(Mutex::new(Stats::default()));
 
     let (input_send, input_recv) = mpsc::channel(128);
+    let (clipboard_send, clipboard_recv) = mpsc::channel(16);
 
-    let server = server::Server::new(config, state.clone(), input_send)?;
+    let server = server::Server::new(
+        config,
+        state.clone(),
+        stats.clone(),
+        input_send,
+        clipboard_recv,
+    )?;
 
     let mut args = std::env::args_os();
 
@@ -67,7 +86,7 @@ async fn async_run() -> Result<()> {
         .status()
         .map_err(Error::from);
 
-    let term_run = term::Term::new(master, state).run(input_recv);
+    let term_run = term::Term::new(master, state, stats, clipboard_send).run(input_recv);
 
     pin_mut!(cmd_run);
     pin_mut!(server_run);
@@ -4,3 +4,8 @@
 bool tiocsctty(int fd) {
     return -1 != ioctl(fd, TIOCSCTTY, 0);
 }
+
+bool tiocswinsz(int fd, unsigned short rows, unsigned short cols) {
+    struct winsize size = { .ws_row = rows, .ws_col = cols };
+    return -1 != ioctl(fd, TIOCSWINSZ, &size);
+}
@@ -4,6 +4,8 @@ use mio::event::Evented;
 use mio::unix::EventedFd;
 use mio::{Poll, PollOpt, Ready, Token};
 
+use muxr_core::state::{Col, Row};
+
 use nix::fcntl::{open, OFlag};
 use nix::pty::{grantpt, posix_openpt, unlockpt, PtyMaster};
 use nix::sys::stat::Mode;
@@ -16,6 +18,7 @@ use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::os::unix::process::CommandExt;
 use std::path::Path;
 use std::process::Command;
+use std::sync::Arc;
 
 use tokio::process::Command as TokioCommand;
 
@@ -68,20 +71,32 @@ pub fn pair() -> Result<(Master, File)> {
     )?;
     let slave = unsafe { File::from_raw_fd(slave_fd) };
 
-    let master = Master(master);
+    let master = Master(Arc::new(master));
 
     Ok((master, slave))
 }
 
-#[derive(Debug)]
-pub struct Master(PtyMaster);
+/// The master side of a pty. Clones share the same file descriptor.
+#[derive(Debug, Clone)]
+pub struct Master(Arc<PtyMaster>);
 
 impl Master {
+    /// Set the window size of the pty, which sends SIGWINCH to its
+    /// foreground process group.
+    pub fn resize(&self, rows: Row, cols: Col) -> io::Result<()> {
+        let result = unsafe { pty_helper::tiocswinsz(self.0.as_raw_fd(), rows.0, cols.0) };
+        if result {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
     fn read(&self, buffer: &mut [u8]) -> io::Result<usize> {
         match unistd::read(self.0.as_raw_fd(), buffer) {
             Ok(sz) => Ok(sz),
             Err(NixError::Sys(e)) => Err(io::Error::from_raw_os_error(e as i32)),
-            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+            Err(e) => Err(io::Error::other(e)),
         }
     }
 
@@ -89,7 +104,7 @@ impl Master {
         match unistd::write(self.0.as_raw_fd(), buffer) {
             Ok(sz) => Ok(sz),
             Err(NixError::Sys(e)) => Err(io::Error::from_raw_os_error(e as i32)),
-            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+            Err(e) => Err(io::Error::other(e)),
         }
     }
 
@@ -161,6 +176,7 @@ mod pty_helper {
 
     extern "C" {
         pub fn tiocsctty(fd: RawFd) -> bool;
+        pub fn tiocswinsz(fd: RawFd, rows: u16, cols: u16) -> bool;
     }
 }
 
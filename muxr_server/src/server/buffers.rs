@@ -0,0 +1,154 @@
+use muxr_core::msg::BufferSummary;
+
+/// The most automatically named buffers kept before the oldest is dropped.
+const LIMIT: usize = 50;
+
+/// How many characters of each buffer are included when listing them.
+const SAMPLE: usize = 50;
+
+#[derive(Debug)]
+struct Buffer {
+    name: String,
+    data: String,
+
+    /// Whether the name was picked by the server rather than a client.
+    automatic: bool,
+}
+
+/// Paste buffers held by the server, most recent first.
+#[derive(Debug, Default)]
+pub struct Buffers {
+    stack: Vec<Buffer>,
+    next: u64,
+}
+
+impl Buffers {
+    /// Push `data` onto the stack as a new, automatically named buffer.
+    pub fn push(&mut self, data: String) {
+        let name = format!("buffer{}", self.next);
+        self.next += 1;
+
+        self.stack.insert(
+            0,
+            Buffer {
+                name,
+                data,
+                automatic: true,
+            },
+        );
+
+        self.trim();
+    }
+
+    /// Replace the contents of the buffer called `name`, creating it on top
+    /// of the stack if there isn't one.
+    pub fn set(&mut self, name: String, data: String) {
+        match self.stack.iter_mut().find(|b| b.name == name) {
+            Some(buffer) => buffer.data = data,
+            None => self.stack.insert(
+                0,
+                Buffer {
+                    name,
+                    data,
+                    automatic: false,
+                },
+            ),
+        }
+    }
+
+    /// The contents of the buffer called `name`, or of the most recent one.
+    pub fn get(&self, name: Option<&str>) -> Option<&str> {
+        self.position(name).map(|i| &*self.stack[i].data)
+    }
+
+    /// Delete the buffer called `name`, or the most recent one. Returns
+    /// whether there was one to delete.
+    pub fn delete(&mut self, name: Option<&str>) -> bool {
+        match self.position(name) {
+            Some(i) => {
+                self.stack.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn list(&self) -> Vec<BufferSummary> {
+        self.stack
+            .iter()
+            .map(|b| BufferSummary {
+                name: b.name.clone(),
+                size: b.data.len(),
+                sample: b.data.chars().take(SAMPLE).collect(),
+            })
+            .collect()
+    }
+
+    fn position(&self, name: Option<&str>) -> Option<usize> {
+        match name {
+            Some(name) => self.stack.iter().position(|b| b.name == name),
+            None if self.stack.is_empty() => None,
+            None => Some(0),
+        }
+    }
+
+    /// Drop the oldest automatically named buffers past the limit. Buffers
+    /// named by a client are only removed when asked.
+    fn trim(&mut self) {
+        let mut automatic = 0;
+
+        self.stack.retain(|b| {
+            automatic += b.automatic as usize;
+            !b.automatic || automatic <= LIMIT
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stack_order() {
+        let mut buffers = Buffers::default();
+        assert_eq!(buffers.get(None), None);
+
+        buffers.push("one".into());
+        buffers.push("two".into());
+        assert_eq!(buffers.get(None), Some("two"));
+        assert_eq!(buffers.get(Some("buffer0")), Some("one"));
+
+        assert!(buffers.delete(None));
+        assert_eq!(buffers.get(None), Some("one"));
+        assert!(!buffers.delete(Some("buffer1")));
+    }
+
+    #[test]
+    fn named_buffers() {
+        let mut buffers = Buffers::default();
+
+        buffers.set("clip".into(), "a".into());
+        buffers.push("b".into());
+        buffers.set("clip".into(), "c".into());
+
+        let names: Vec<_> = buffers.list().into_iter().map(|b| b.name).collect();
+        assert_eq!(names, ["buffer0", "clip"]);
+        assert_eq!(buffers.get(Some("clip")), Some("c"));
+    }
+
+    #[test]
+    fn only_automatic_buffers_are_dropped() {
+        let mut buffers = Buffers::default();
+
+        buffers.set("keep".into(), "k".into());
+
+        for i in 0..LIMIT + 5 {
+            buffers.push(i.to_string());
+        }
+
+        let list = buffers.list();
+        assert_eq!(list.len(), LIMIT + 1);
+        assert_eq!(list[0].sample, (LIMIT + 4).to_string());
+        assert_eq!(buffers.get(Some("keep")), Some("k"));
+    }
+}
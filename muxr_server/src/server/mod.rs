@@ -1,14 +1,18 @@
+mod buffers;
 mod client;
 
-use crate::config;
+use crate::config::{self, ClipboardRead};
 use crate::error::{Result, ResultExt};
+use crate::term::{Clipboard, Request};
 
 use futures_util::future;
 use futures_util::stream::StreamExt;
 
-use muxr_core::input::{Event, Key};
+use muxr_core::msg::{ClientMessage, ServerMessage};
 use muxr_core::state::State;
+use muxr_core::stats::Stats;
 
+use self::buffers::Buffers;
 use self::client::Client;
 
 use serde::de::DeserializeOwned;
@@ -26,8 +30,11 @@ struct Inner {
     config: config::Server,
     clients: Mutex<Vec<Client>>,
     state: Arc<Mutex<State>>,
+    stats: Arc<Mutex<Stats>>,
+    buffers: Mutex<Buffers>,
     socket: Mutex<Option<UnixListener>>,
-    input_sender: Sender<Event>,
+    input_sender: Sender<Request>,
+    clipboard: Mutex<Option<Receiver<Clipboard>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -37,15 +44,20 @@ impl Server {
     pub fn new(
         config: config::Server,
         state: Arc<Mutex<State>>,
-        input_sender: Sender<Event>,
+        stats: Arc<Mutex<Stats>>,
+        input_sender: Sender<Request>,
+        clipboard: Receiver<Clipboard>,
     ) -> Result<Self> {
         let socket = UnixListener::bind(&config.socket_path)?;
 
         let server = Server(Arc::new(Inner {
             clients: Default::default(),
+            buffers: Default::default(),
             socket: Mutex::new(Some(socket)),
             input_sender,
+            clipboard: Mutex::new(Some(clipboard)),
             state,
+            stats,
             config,
         }));
 
@@ -55,8 +67,11 @@ impl Server {
     pub async fn run(self) -> Result<()> {
         let accept = self.clone().accept_loop();
         let state = self.clone().state_loop();
+        let clipboard = self.clone().clipboard_loop();
 
-        future::try_join(accept, state).await.map(|_| ())
+        future::try_join3(accept, state, clipboard)
+            .await
+            .map(|_| ())
     }
 
     async fn accept_loop(self) -> Result<()> {
@@ -86,10 +101,11 @@ impl Server {
 
             let (write_send, write_recv) = channel(1);
 
-            tokio::spawn(
-                self.clone()
-                    .client_read_loop(read, self.0.input_sender.clone()),
-            );
+            tokio::spawn(self.clone().client_read_loop(
+                read,
+                write_send.clone(),
+                self.0.input_sender.clone(),
+            ));
             tokio::spawn(self.clone().client_write_loop(write, write_recv));
 
             self.0.clients.lock().await.push(Client::new(write_send));
@@ -111,50 +127,174 @@ impl Server {
 
             let bytes = {
                 let state = self.0.state.lock().await;
-                muxr_core::msg::serialize(&*state)?
+                muxr_core::msg::serialize(&ServerMessage::State(Box::new(state.clone())))?
             };
 
-            let mut clients = self.0.clients.lock().await;
+            self.broadcast(bytes).await;
+        }
+    }
 
-            let sends = clients.drain(..).map(|mut client| {
-                async {
-                    match client.send(bytes.clone()).await {
-                        Ok(_) => Some(client),
-                        Err(e) => {
-                            eprintln!("state_loop error: {:?}", e);
-                            None
-                        }
+    /// Act on OSC 52 requests from the child. Copied text goes into a new
+    /// paste buffer, and reads are answered according to the config.
+    async fn clipboard_loop(self) -> Result<()> {
+        let mut recv = self
+            .0
+            .clipboard
+            .lock()
+            .await
+            .take()
+            .chain_err(|| "server can only be started once")?;
+
+        while let Some(request) = recv.recv().await {
+            match request {
+                Clipboard::Set { selection, data } => {
+                    if !data.is_empty() {
+                        self.0.buffers.lock().await.push(data.clone());
                     }
-                }
-            });
 
-            *clients = future::join_all(sends)
-                .await
-                .into_iter()
-                .filter_map(|x| x)
-                .collect();
+                    if self.0.config.forward_clipboard {
+                        let msg = ServerMessage::Clipboard(selection, data);
+                        self.broadcast(muxr_core::msg::serialize(&msg)?).await;
+                    }
+                }
+                Clipboard::Query {
+                    selection,
+                    terminator,
+                } => {
+                    let reply = clipboard_reply(
+                        self.0.config.clipboard_read,
+                        &*self.0.buffers.lock().await,
+                        &selection,
+                        terminator,
+                    );
+
+                    let request = match reply {
+                        Some(reply) => Request::Reply(reply),
+                        None => continue,
+                    };
+
+                    if self.0.input_sender.clone().send(request).await.is_err() {
+                        break;
+                    }
+                }
+            }
         }
+
+        Ok(())
+    }
+
+    /// Send `bytes` to every client, dropping any that can't be reached.
+    async fn broadcast(&self, bytes: Vec<u8>) {
+        let mut clients = self.0.clients.lock().await;
+
+        let sends = clients.drain(..).map(|mut client| async {
+            match client.send(bytes.clone()).await {
+                Ok(_) => Some(client),
+                Err(e) => {
+                    eprintln!("broadcast error: {:?}", e);
+                    None
+                }
+            }
+        });
+
+        *clients = future::join_all(sends)
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
     }
 
-    async fn client_read_loop(self, client: ReadHalf<UnixStream>, sender: Sender<Event>) {
-        self.client_read(client, sender).await.unwrap();
+    async fn client_read_loop(
+        self,
+        client: ReadHalf<UnixStream>,
+        reply: Sender<Vec<u8>>,
+        sender: Sender<Request>,
+    ) {
+        self.client_read(client, reply, sender).await.unwrap();
     }
 
     async fn client_read(
         self,
         mut client: ReadHalf<UnixStream>,
-        mut sender: Sender<Event>,
+        mut reply: Sender<Vec<u8>>,
+        mut sender: Sender<Request>,
     ) -> Result<()> {
         loop {
-            let event: Event = deserialize_from(&mut client).await?;
+            let msg: ClientMessage = deserialize_from(&mut client).await?;
 
-            match event {
-                Event::Key(Key::Char(k)) => {
-                    if let Err(_) = sender.send(Event::Key(Key::Char(k))).await {
+            match msg {
+                ClientMessage::Event(event) => {
+                    if sender.send(Request::Event(event)).await.is_err() {
+                        break;
+                    }
+                }
+                ClientMessage::Resize(rows, cols) => {
+                    // TODO: With several clients attached, the last one to
+                    // resize wins.
+                    if sender.send(Request::Resize(rows, cols)).await.is_err() {
                         break;
                     }
                 }
-                _ => (), // TODO: Handle all other types of characters.
+                ClientMessage::QueryStats => {
+                    let stats = self.0.stats.lock().await.clone();
+                    let bytes = muxr_core::msg::serialize(&ServerMessage::Stats(stats))?;
+
+                    if reply.send(bytes).await.is_err() {
+                        break;
+                    }
+                }
+                ClientMessage::QueryHistory => {
+                    let history = self.0.state.lock().await.history().clone();
+                    let bytes = muxr_core::msg::serialize(&ServerMessage::History(history))?;
+
+                    if reply.send(bytes).await.is_err() {
+                        break;
+                    }
+                }
+                ClientMessage::SetBuffer(None, data) => self.0.buffers.lock().await.push(data),
+                ClientMessage::SetBuffer(Some(name), data) => {
+                    self.0.buffers.lock().await.set(name, data)
+                }
+                ClientMessage::ListBuffers => {
+                    let list = self.0.buffers.lock().await.list();
+                    let bytes = muxr_core::msg::serialize(&ServerMessage::Buffers(list))?;
+
+                    if reply.send(bytes).await.is_err() {
+                        break;
+                    }
+                }
+                ClientMessage::ShowBuffer(name) => {
+                    let data = self
+                        .0
+                        .buffers
+                        .lock()
+                        .await
+                        .get(name.as_deref())
+                        .map(String::from);
+                    let bytes = muxr_core::msg::serialize(&ServerMessage::Buffer(data))?;
+
+                    if reply.send(bytes).await.is_err() {
+                        break;
+                    }
+                }
+                ClientMessage::DeleteBuffer(name) => {
+                    self.0.buffers.lock().await.delete(name.as_deref());
+                }
+                ClientMessage::PasteBuffer(name) => {
+                    let data = self
+                        .0
+                        .buffers
+                        .lock()
+                        .await
+                        .get(name.as_deref())
+                        .map(String::from);
+
+                    if let Some(data) = data {
+                        if sender.send(Request::Paste(data)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
             }
         }
 
@@ -178,6 +318,26 @@ impl Server {
     }
 }
 
+/// The answer to an OSC 52 query for `selection`, if `policy` allows one.
+/// There's only the one stack of paste buffers, so every selection, like `c`
+/// for the clipboard or `p` for the primary selection, gets the most recent
+/// buffer.
+fn clipboard_reply(
+    policy: ClipboardRead,
+    buffers: &Buffers,
+    selection: &str,
+    terminator: &str,
+) -> Option<Vec<u8>> {
+    if policy == ClipboardRead::Deny {
+        return None;
+    }
+
+    let data = buffers.get(None).map(base64::encode).unwrap_or_default();
+    let reply = format!("\x1b]52;{};{}{}", selection, data, terminator);
+
+    Some(reply.into_bytes())
+}
+
 async fn deserialize_from<T: DeserializeOwned>(reader: &mut ReadHalf<UnixStream>) -> Result<T> {
     let mut sz_buf = [0u8; std::mem::size_of::<usize>()];
     reader.read_exact(&mut sz_buf).await?;
@@ -189,3 +349,28 @@ async fn deserialize_from<T: DeserializeOwned>(reader: &mut ReadHalf<UnixStream>
 
     Ok(bincode::deserialize(&msg_buf)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clipboard_reply_policy() {
+        let mut buffers = Buffers::default();
+
+        let reply = clipboard_reply(ClipboardRead::Buffer, &buffers, "c", "\x07");
+        assert_eq!(reply.unwrap(), b"\x1b]52;c;\x07");
+
+        buffers.push("hello".into());
+
+        let reply = clipboard_reply(ClipboardRead::Deny, &buffers, "c", "\x07");
+        assert_eq!(reply, None);
+
+        let reply = clipboard_reply(ClipboardRead::Buffer, &buffers, "s0", "\x1b\\");
+        assert_eq!(reply.unwrap(), b"\x1b]52;s0;aGVsbG8=\x1b\\");
+
+        // Selections aren't kept apart.
+        let reply = clipboard_reply(ClipboardRead::Buffer, &buffers, "p", "\x07");
+        assert_eq!(reply.unwrap(), b"\x1b]52;p;aGVsbG8=\x07");
+    }
+}
@@ -0,0 +1,296 @@
+use muxr_core::input::{Key, KeyEvent, KeyKind, Modifiers};
+use muxr_core::state::{Mode, State};
+
+/// The bytes a terminal sends for `event`, the way xterm does, given the
+/// modes the child has set.
+///
+/// Keypad application mode only changes what the numeric keypad sends, and
+/// the client can't tell those keys apart from the main keyboard, so it has no
+/// effect here. Releases aren't reported to the child at all.
+pub fn encode(event: &KeyEvent, state: &State) -> Vec<u8> {
+    if event.kind == KeyKind::Release {
+        return Vec::new();
+    }
+
+    let modes = state.modes();
+    let modifiers = event.modifiers;
+
+    // Keys that send SS3 in application cursor mode and CSI otherwise, or
+    // `CSI 1 ; m` with modifiers.
+    let cursor = |c: char| {
+        if !modifiers.is_empty() {
+            format!("\x1b[1;{}{}", parameter(modifiers), c).into_bytes()
+        } else if modes.contains(Mode::APP_CURSOR) {
+            format!("\x1bO{}", c).into_bytes()
+        } else {
+            format!("\x1b[{}", c).into_bytes()
+        }
+    };
+
+    match event.key {
+        Key::Up => cursor('A'),
+        Key::Down => cursor('B'),
+        Key::Right => cursor('C'),
+        Key::Left => cursor('D'),
+        Key::Home => cursor('H'),
+        Key::End => cursor('F'),
+        Key::Insert => tilde(2, modifiers),
+        Key::Delete => tilde(3, modifiers),
+        Key::PageUp => tilde(5, modifiers),
+        Key::PageDown => tilde(6, modifiers),
+        Key::F(n) => function(n, modifiers),
+        Key::Tab if modifiers == Modifiers::SHIFT => b"\x1b[Z".to_vec(),
+        Key::Enter | Key::Tab | Key::Backspace | Key::Esc | Key::Char(_) => {
+            text(&event.key, modifiers, modes, state.modify_other_keys())
+        }
+        Key::__NonExhaustive => Vec::new(),
+    }
+}
+
+/// xterm's modifier parameter.
+fn parameter(modifiers: Modifiers) -> u8 {
+    1 + modifiers.bits()
+}
+
+fn tilde(code: u8, modifiers: Modifiers) -> Vec<u8> {
+    if modifiers.is_empty() {
+        format!("\x1b[{}~", code).into_bytes()
+    } else {
+        format!("\x1b[{};{}~", code, parameter(modifiers)).into_bytes()
+    }
+}
+
+/// F1-F12, and F13-F24 as xterm sends them: F1-F12 with Shift held.
+fn function(n: u8, mut modifiers: Modifiers) -> Vec<u8> {
+    let n = match n {
+        1..=12 => n,
+        13..=24 => {
+            modifiers |= Modifiers::SHIFT;
+            n - 12
+        }
+        _ => return Vec::new(),
+    };
+
+    let code = match n {
+        1..=4 => {
+            let last = (b'P' + n - 1) as char;
+
+            return if modifiers.is_empty() {
+                format!("\x1bO{}", last).into_bytes()
+            } else {
+                format!("\x1b[1;{}{}", parameter(modifiers), last).into_bytes()
+            };
+        }
+        5 => 15,
+        6..=10 => n + 11,
+        _ => n + 12,
+    };
+
+    tilde(code, modifiers)
+}
+
+/// Keys that send text. The usual encodings can only show Ctrl for some keys,
+/// Alt as an escape prefix, and Shift by the character itself; with
+/// modifyOtherKeys the child can ask for the rest, or everything, as
+/// `CSI 27 ; m ; code ~`.
+fn text(key: &Key, modifiers: Modifiers, modes: Mode, modify_other_keys: u8) -> Vec<u8> {
+    let (mut bytes, code) = match *key {
+        Key::Enter if modes.contains(Mode::LINEFEED_NEWLINE) => (b"\r\n".to_vec(), 13),
+        Key::Enter => (b"\r".to_vec(), 13),
+        Key::Tab => (b"\t".to_vec(), 9),
+        Key::Backspace => (vec![0x7f], 127),
+        Key::Esc => (vec![0x1b], 27),
+        Key::Char(c) => (char_bytes(c), c as u32),
+        _ => return Vec::new(),
+    };
+
+    let mut lost = modifiers - Modifiers::ALT;
+
+    let ctrl = match *key {
+        Key::Char(c) if modifiers.contains(Modifiers::CTRL) => control(c),
+        Key::Backspace if modifiers.contains(Modifiers::CTRL) => Some(0x08),
+        _ => None,
+    };
+
+    // Shift is already in the character, unless it's been replaced by a
+    // control character.
+    match (ctrl, key) {
+        (Some(byte), _) => {
+            bytes = vec![byte];
+            lost -= Modifiers::CTRL;
+        }
+        (None, Key::Char(_)) => lost -= Modifiers::SHIFT,
+        (None, _) => (),
+    }
+
+    let other = match modify_other_keys {
+        0 => false,
+        1 => !lost.is_empty(),
+        _ => !(modifiers - Modifiers::SHIFT).is_empty() || !lost.is_empty(),
+    };
+
+    if other {
+        return format!("\x1b[27;{};{}~", parameter(modifiers), code).into_bytes();
+    }
+
+    if modifiers.contains(Modifiers::ALT) {
+        bytes.insert(0, 0x1b);
+    }
+
+    bytes
+}
+
+fn char_bytes(c: char) -> Vec<u8> {
+    let mut buf = [0; 4];
+    c.encode_utf8(&mut buf).as_bytes().to_vec()
+}
+
+/// The control character sent when Ctrl is held with `c`, if there is one.
+/// Digits follow xterm, which maps the ones on the same keys as the
+/// characters below to the same controls.
+fn control(c: char) -> Option<u8> {
+    let byte = match c.to_ascii_lowercase() {
+        c @ 'a'..='z' => c as u8 - b'a' + 1,
+        '@' | ' ' | '2' => 0x00,
+        '[' | '3' => 0x1b,
+        '\\' | '4' => 0x1c,
+        ']' | '5' => 0x1d,
+        '^' | '6' => 0x1e,
+        '_' | '/' | '7' => 0x1f,
+        '?' | '8' => 0x7f,
+        _ => return None,
+    };
+
+    Some(byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enc(key: Key) -> Vec<u8> {
+        encode(&key.into(), &State::default())
+    }
+
+    fn with(key: Key, modifiers: Modifiers) -> Vec<u8> {
+        encode(&KeyEvent::new(key, modifiers), &State::default())
+    }
+
+    fn with_state(key: Key, modifiers: Modifiers, state: &State) -> Vec<u8> {
+        encode(&KeyEvent::new(key, modifiers), state)
+    }
+
+    #[test]
+    fn characters() {
+        assert_eq!(enc(Key::Char('a')), b"a");
+        assert_eq!(enc(Key::Char('é')), "é".as_bytes());
+        assert_eq!(with(Key::Char('A'), Modifiers::SHIFT), b"A");
+        assert_eq!(enc(Key::Enter), b"\r");
+        assert_eq!(enc(Key::Tab), b"\t");
+
+        let mut state = State::default();
+        state.set_mode(Mode::LINEFEED_NEWLINE, true);
+        assert_eq!(encode(&Key::Enter.into(), &state), b"\r\n");
+    }
+
+    #[test]
+    fn control_and_alt() {
+        assert_eq!(with(Key::Char('c'), Modifiers::CTRL), b"\x03");
+        assert_eq!(with(Key::Char('['), Modifiers::CTRL), b"\x1b");
+        assert_eq!(with(Key::Char('4'), Modifiers::CTRL), b"\x1c");
+        assert_eq!(with(Key::Char(' '), Modifiers::CTRL), b"\x00");
+        assert_eq!(with(Key::Char('x'), Modifiers::ALT), b"\x1bx");
+        assert_eq!(with(Key::Enter, Modifiers::ALT), b"\x1b\r");
+        assert_eq!(
+            with(Key::Char('c'), Modifiers::CTRL | Modifiers::ALT),
+            b"\x1b\x03"
+        );
+        assert_eq!(with(Key::Backspace, Modifiers::CTRL), b"\x08");
+        assert_eq!(enc(Key::Esc), b"\x1b");
+        assert_eq!(enc(Key::Backspace), b"\x7f");
+
+        // Modifiers that can't be expressed are dropped.
+        assert_eq!(with(Key::Enter, Modifiers::CTRL), b"\r");
+        assert_eq!(with(Key::Char('x'), Modifiers::SUPER), b"x");
+    }
+
+    #[test]
+    fn cursor_keys() {
+        assert_eq!(enc(Key::Up), b"\x1b[A");
+        assert_eq!(enc(Key::Left), b"\x1b[D");
+        assert_eq!(enc(Key::Home), b"\x1b[H");
+        assert_eq!(with(Key::Right, Modifiers::CTRL), b"\x1b[1;5C");
+        assert_eq!(
+            with(Key::Left, Modifiers::CTRL | Modifiers::SHIFT),
+            b"\x1b[1;6D"
+        );
+
+        let mut state = State::default();
+        state.set_mode(Mode::APP_CURSOR, true);
+        assert_eq!(encode(&Key::Up.into(), &state), b"\x1bOA");
+        assert_eq!(encode(&Key::End.into(), &state), b"\x1bOF");
+        assert_eq!(with_state(Key::Up, Modifiers::ALT, &state), b"\x1b[1;3A");
+
+        // Only cursor keys change in application mode.
+        assert_eq!(encode(&Key::PageUp.into(), &state), b"\x1b[5~");
+        assert_eq!(enc(Key::Delete), b"\x1b[3~");
+        assert_eq!(with(Key::Delete, Modifiers::SHIFT), b"\x1b[3;2~");
+        assert_eq!(with(Key::Tab, Modifiers::SHIFT), b"\x1b[Z");
+    }
+
+    #[test]
+    fn function_keys() {
+        assert_eq!(enc(Key::F(1)), b"\x1bOP");
+        assert_eq!(enc(Key::F(4)), b"\x1bOS");
+        assert_eq!(enc(Key::F(5)), b"\x1b[15~");
+        assert_eq!(enc(Key::F(6)), b"\x1b[17~");
+        assert_eq!(enc(Key::F(10)), b"\x1b[21~");
+        assert_eq!(enc(Key::F(11)), b"\x1b[23~");
+        assert_eq!(enc(Key::F(12)), b"\x1b[24~");
+        assert_eq!(enc(Key::F(13)), b"\x1b[1;2P");
+        assert_eq!(enc(Key::F(17)), b"\x1b[15;2~");
+        assert_eq!(enc(Key::F(24)), b"\x1b[24;2~");
+        assert_eq!(enc(Key::F(25)), b"");
+        assert_eq!(with(Key::F(5), Modifiers::ALT), b"\x1b[15;3~");
+        assert_eq!(with(Key::F(2), Modifiers::CTRL), b"\x1b[1;5Q");
+    }
+
+    #[test]
+    fn modify_other_keys() {
+        let mut state = State::default();
+        state.set_modify_other_keys(1);
+
+        let ctrl_shift = Modifiers::CTRL | Modifiers::SHIFT;
+
+        // Level 1 only changes keys with no other way to be sent.
+        assert_eq!(with_state(Key::Char('a'), Modifiers::CTRL, &state), b"\x01");
+        assert_eq!(
+            with_state(Key::Char('A'), ctrl_shift, &state),
+            b"\x1b[27;6;65~"
+        );
+        assert_eq!(
+            with_state(Key::Enter, Modifiers::CTRL, &state),
+            b"\x1b[27;5;13~"
+        );
+        assert_eq!(with_state(Key::Char('A'), Modifiers::SHIFT, &state), b"A");
+
+        state.set_modify_other_keys(2);
+        assert_eq!(
+            with_state(Key::Char('a'), Modifiers::CTRL, &state),
+            b"\x1b[27;5;97~"
+        );
+        assert_eq!(
+            with_state(Key::Char('x'), Modifiers::ALT, &state),
+            b"\x1b[27;3;120~"
+        );
+        assert_eq!(with_state(Key::Char('A'), Modifiers::SHIFT, &state), b"A");
+    }
+
+    #[test]
+    fn releases_are_ignored() {
+        let mut event = KeyEvent::from(Key::Char('a'));
+        event.kind = KeyKind::Release;
+
+        assert_eq!(encode(&event, &State::default()), b"");
+    }
+}
@@ -1,36 +1,100 @@
+mod keys;
+mod mouse;
+
 use crate::error::*;
 use crate::pty;
 
+use futures_util::stream::{self, StreamExt};
 use futures_util::{future, pin_mut};
 
-use muxr_core::input::{Event, Key};
-use muxr_core::state::State;
+use muxr_core::input::Event;
+use muxr_core::state::{
+    CellStyle, Charset, CharsetSlot, Col, Color, Cursor, Erase, Mode, MouseEncoding, MouseTracking,
+    Row, State,
+};
+use muxr_core::stats::Stats;
 
 use std::sync::Arc;
 
 use tokio::io::{AsyncReadExt, AsyncWriteExt, PollEvented, ReadHalf, WriteHalf};
-use tokio::sync::mpsc::Receiver;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{self, Receiver, Sender, UnboundedSender};
 use tokio::sync::Mutex;
 
-use vte::{Parser, Perform};
+use vte::{Params, ParamsIter, Parser, Perform};
+
+/// Something a client has asked the terminal to do.
+#[derive(Debug, Clone)]
+pub enum Request {
+    Event(Event),
+    Resize(Row, Col),
+    /// Write the contents of a paste buffer.
+    Paste(String),
+    /// Answer a query from the child, like an OSC 4 color request.
+    Reply(Vec<u8>),
+}
+
+/// A clipboard request the child made with OSC 52, which the server acts on
+/// since it holds the paste buffers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Clipboard {
+    /// Copy `data` to the selections named in `selection`, like `c` for the
+    /// clipboard or `p` for the primary selection.
+    Set { selection: String, data: String },
+    /// Ask for the contents of `selection`. Any reply has to end with
+    /// `terminator`, as the request did.
+    Query {
+        selection: String,
+        terminator: &'static str,
+    },
+}
 
 #[derive(Debug)]
 pub struct Term {
     state: Arc<Mutex<State>>,
+    stats: Arc<Mutex<Stats>>,
     master: pty::Master,
+    clipboard: Sender<Clipboard>,
 }
 
 impl Term {
-    pub fn new(master: pty::Master, state: Arc<Mutex<State>>) -> Self {
-        Term { state, master }
+    pub fn new(
+        master: pty::Master,
+        state: Arc<Mutex<State>>,
+        stats: Arc<Mutex<Stats>>,
+        clipboard: Sender<Clipboard>,
+    ) -> Self {
+        Term {
+            state,
+            stats,
+            master,
+            clipboard,
+        }
     }
 
-    pub async fn run(self, recv: Receiver<Event>) -> Result<()> {
+    pub async fn run(self, recv: Receiver<Request>) -> Result<()> {
+        {
+            let state = self.state.lock().await;
+            self.master.resize(state.rows(), state.columns())?;
+        }
+
+        let master = self.master.clone();
         let evented = PollEvented::new(self.master)?;
         let (read, write) = tokio::io::split(evented);
 
-        let f0 = Self::write_loop(recv, write);
-        let f1 = Self::read_loop(self.state.clone(), read);
+        // Replies go through the write loop, so they can't be interleaved
+        // with a paste or key being written.
+        let (reply_send, reply_recv) = mpsc::unbounded_channel();
+        let requests = stream::select(recv, reply_recv.map(Request::Reply));
+
+        let f0 = Self::write_loop(requests, self.state.clone(), master, write);
+        let f1 = Self::read_loop(
+            self.state.clone(),
+            self.stats.clone(),
+            reply_send,
+            self.clipboard,
+            read,
+        );
 
         pin_mut!(f0);
         pin_mut!(f1);
@@ -39,18 +103,47 @@ impl Term {
     }
 
     async fn write_loop(
-        mut recv: Receiver<Event>,
+        mut requests: impl stream::Stream<Item = Request> + Unpin,
+        state: Arc<Mutex<State>>,
+        master: pty::Master,
         mut write: WriteHalf<PollEvented<pty::Master>>,
     ) -> Result<()> {
-        while let Some(event) = recv.recv().await {
-            match event {
-                Event::Key(Key::Char(c)) => {
-                    let s = c.to_string();
+        while let Some(request) = requests.next().await {
+            let bytes = match request {
+                Request::Event(Event::Key(key)) => keys::encode(&key, &*state.lock().await),
+                Request::Event(Event::Mouse(mouse)) => {
+                    match mouse::encode(&mouse, &*state.lock().await) {
+                        Some(bytes) => bytes,
+                        None => continue,
+                    }
+                }
+                Request::Event(Event::Focus(focused)) => {
+                    if !state.lock().await.modes().contains(Mode::FOCUS_REPORT) {
+                        continue;
+                    }
 
-                    write.write_all(s.as_bytes()).await?;
+                    if focused {
+                        b"\x1b[I".to_vec()
+                    } else {
+                        b"\x1b[O".to_vec()
+                    }
                 }
-                _ => unimplemented!(),
-            }
+                Request::Event(Event::Paste(data)) | Request::Paste(data) => {
+                    let bracketed = state.lock().await.modes().contains(Mode::BRACKETED_PASTE);
+                    paste_bytes(&data, bracketed)
+                }
+                Request::Resize(rows, cols) => {
+                    // Resize the grid first, so the child's redraw after
+                    // SIGWINCH lands on a screen of the right size.
+                    let mut state = state.lock().await;
+                    state.resize(rows, cols);
+                    master.resize(state.rows(), state.columns())?;
+                    continue;
+                }
+                Request::Reply(bytes) => bytes,
+            };
+
+            write.write_all(&bytes).await?;
         }
 
         Ok(())
@@ -58,6 +151,9 @@ impl Term {
 
     async fn read_loop(
         state: Arc<Mutex<State>>,
+        stats: Arc<Mutex<Stats>>,
+        reply: UnboundedSender<Vec<u8>>,
+        mut clipboard: Sender<Clipboard>,
         mut read: ReadHalf<PollEvented<pty::Master>>,
     ) -> Result<()> {
         let mut buf = [0u8; 1024];
@@ -66,55 +162,719 @@ impl Term {
         loop {
             let len: usize = read.read(&mut buf).await?;
             let bytes = &buf[0..len];
+            let mut output = Output::default();
 
-            let mut locked = state.lock().await;
-            let mut perform = StatePerform(&mut locked);
+            {
+                let mut locked = state.lock().await;
+                let mut locked_stats = stats.lock().await;
+                let mut perform = StatePerform(&mut locked, &mut locked_stats, &mut output);
 
-            for byte in bytes {
-                parser.advance(&mut perform, *byte);
+                for byte in bytes {
+                    parser.advance(&mut perform, *byte);
+                }
+            }
+
+            // The write loop and server only stop if the terminal is going
+            // away.
+            if !output.replies.is_empty() && reply.send(output.replies).is_err() {
+                return Ok(());
+            }
+
+            // Waiting for a busy server would hold up the child's output, so
+            // clipboard requests it can't keep up with are dropped.
+            for request in output.clipboard {
+                match clipboard.try_send(request) {
+                    Ok(()) => (),
+                    Err(TrySendError::Full(request)) => {
+                        debug!("dropped clipboard request {:?}", request);
+                    }
+                    Err(TrySendError::Closed(_)) => return Ok(()),
+                }
             }
         }
     }
 }
 
+/// Encode pasted text the way a terminal would: newlines become carriage
+/// returns, as if Enter had been pressed, and if the child asked for it, the
+/// text is bracketed so it can tell the paste from typing.
+fn paste_bytes(data: &str, bracketed: bool) -> Vec<u8> {
+    const START: &str = "\x1b[200~";
+    const END: &str = "\x1b[201~";
+
+    let mut text = data.replace("\r\n", "\r").replace('\n', "\r");
+
+    if !bracketed {
+        return text.into_bytes();
+    }
+
+    // Don't let the paste end the bracket early.
+    while text.contains(END) {
+        text = text.replace(END, "");
+    }
+
+    format!("{}{}{}", START, text, END).into_bytes()
+}
+
+/// What parsing output from the child produced, other than changes to the
+/// state.
+#[derive(Debug, Default)]
+struct Output {
+    /// Bytes to write back to the child, like the answer to a color query.
+    replies: Vec<u8>,
+    clipboard: Vec<Clipboard>,
+}
+
 #[derive(Debug)]
-struct StatePerform<'a>(pub &'a mut State);
+struct StatePerform<'a>(pub &'a mut State, pub &'a mut Stats, pub &'a mut Output);
 
 impl<'a> Perform for StatePerform<'a> {
     fn print(&mut self, c: char) {
+        // DEL arrives as a printable character, but should be ignored.
+        if c as u32 == u32::from(C0::DEL) {
+            return;
+        }
+
         self.0.print(c)
     }
 
     fn execute(&mut self, byte: u8) {
         match byte {
+            // The parser has already aborted any sequence in progress.
+            C0::NUL | C0::CAN | C0::SUB => (),
+            C0::BEL => self.0.ring_bell(),
+            C0::BS => self.0.move_backward(Col(1)),
+            C0::SO => self.0.invoke_charset(CharsetSlot::G1),
+            C0::SI => self.0.invoke_charset(CharsetSlot::G0),
             C0::CR => self.0.carriage_return(),
-            C0::LF | C0::VT | C0::FF => self.0.linefeed(),
-            _ => eprintln!("[UNIMPL] execute({:02x})", byte),
+            C0::HT => self.0.tab(1),
+            C1::HTS => self.0.set_tab_stop(),
+            C0::LF | C0::VT | C0::FF => {
+                if self.0.modes().contains(Mode::LINEFEED_NEWLINE) {
+                    self.0.carriage_return();
+                }
+
+                self.0.linefeed();
+            }
+            C1::IND => self.0.linefeed(),
+            C1::NEL => {
+                self.0.carriage_return();
+                self.0.linefeed();
+            }
+            C1::RI => self.0.reverse_index(),
+            _ => {
+                self.1.unhandled_execute += 1;
+                debug!("unhandled execute({:02x})", byte);
+            }
+        }
+    }
+
+    fn hook(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        self.1.unhandled_dcs += 1;
+        debug!(
+            "unhandled hook({:?}, {:?}, {:?}, {:?})",
+            params, intermediates, ignore, action
+        );
+    }
+
+    // No device control strings are handled, so `hook` has already counted
+    // the sequence and there is nothing to do with its contents.
+    fn put(&mut self, _: u8) {}
+
+    fn unhook(&mut self) {}
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        let unimpl = |stats: &mut Stats| {
+            stats.unhandled_osc += 1;
+            debug!(
+                "unhandled osc_dispatch({:?}, {:?})",
+                params, bell_terminated
+            );
+        };
+
+        // Replies end the same way as the query did.
+        let terminator = if bell_terminated { "\x07" } else { "\x1b\\" };
+
+        let (command, args) = match params.split_first() {
+            Some(split) => split,
+            None => return unimpl(self.1),
+        };
+
+        match *command {
+            b"0" | b"1" | b"2" => {
+                // The parser splits on every semicolon, even in the title.
+                let text = String::from_utf8_lossy(&args.join(&b';')).into_owned();
+
+                if *command != b"2" {
+                    self.0.set_icon_name(text.clone());
+                }
+
+                if *command != b"1" {
+                    self.0.set_title(text);
+                }
+            }
+            b"7" => match args.first().and_then(|url| file_url_path(url)) {
+                Some(path) => self.0.set_working_directory(Some(path)),
+                None => unimpl(self.1),
+            },
+            b"4" => {
+                for pair in args.chunks(2) {
+                    let idx = match pair {
+                        [idx, _] => parse_number(idx),
+                        _ => None,
+                    };
+
+                    match (idx, pair.get(1)) {
+                        (Some(idx), Some(&b"?")) => {
+                            let color = color_spec(self.0.palette(idx));
+                            let reply = format!("\x1b]4;{};{}{}", idx, color, terminator);
+                            self.2.replies.extend_from_slice(reply.as_bytes());
+                        }
+                        (Some(idx), Some(spec)) => match parse_color(spec) {
+                            Some(color) => self.0.set_palette(idx, Some(color)),
+                            None => unimpl(self.1),
+                        },
+                        _ => unimpl(self.1),
+                    }
+                }
+            }
+            b"10" | b"11" | b"12" => {
+                // Each extra argument applies to the next color in turn, so
+                // `OSC 10 ; ? ; ?` asks for the foreground and background.
+                let first = match *command {
+                    b"10" => 10,
+                    b"11" => 11,
+                    _ => 12,
+                };
+
+                for (number, spec) in (first..=12).zip(args.iter()) {
+                    if *spec == b"?" {
+                        let color = match number {
+                            10 => self.0.default_foreground(),
+                            11 => self.0.default_background(),
+                            _ => self.0.cursor.color,
+                        };
+
+                        let reply = format!("\x1b]{};{}{}", number, color_spec(color), terminator);
+                        self.2.replies.extend_from_slice(reply.as_bytes());
+                        continue;
+                    }
+
+                    let color = match parse_color(spec) {
+                        Some(color) => color,
+                        None => {
+                            unimpl(self.1);
+                            continue;
+                        }
+                    };
+
+                    match number {
+                        10 => self.0.set_default_foreground(Some(color)),
+                        11 => self.0.set_default_background(Some(color)),
+                        _ => self.0.cursor.color = color,
+                    }
+                }
+            }
+            b"104" => {
+                let indices: Vec<_> = args.iter().filter(|a| !a.is_empty()).collect();
+
+                if indices.is_empty() {
+                    self.0.reset_palette();
+                }
+
+                for idx in indices {
+                    match parse_number(idx) {
+                        Some(idx) => self.0.set_palette(idx, None),
+                        None => unimpl(self.1),
+                    }
+                }
+            }
+            b"52" => match args {
+                [selection, data] => {
+                    // xterm takes no selection to mean the primary selection
+                    // and cut buffer 0.
+                    let selection = match *selection {
+                        b"" => "s0".into(),
+                        _ => String::from_utf8_lossy(selection).into_owned(),
+                    };
+
+                    if *data == b"?" {
+                        self.2.clipboard.push(Clipboard::Query {
+                            selection,
+                            terminator,
+                        });
+                        return;
+                    }
+
+                    match base64::decode(data) {
+                        Ok(data) => self.2.clipboard.push(Clipboard::Set {
+                            selection,
+                            data: String::from_utf8_lossy(&data).into_owned(),
+                        }),
+                        Err(_) => unimpl(self.1),
+                    }
+                }
+                _ => unimpl(self.1),
+            },
+            b"110" => self.0.set_default_foreground(None),
+            b"111" => self.0.set_default_background(None),
+            b"112" => self.0.cursor.color = Cursor::default().color,
+            _ => unimpl(self.1),
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        let unimpl = |stats: &mut Stats| {
+            stats.unhandled_csi += 1;
+            debug!(
+                "unhandled csi_dispatch({:?}, {:?}, {:?}, {:?})",
+                params, intermediates, ignore, action
+            );
+        };
+
+        if ignore {
+            unimpl(self.1);
+            return;
+        }
+
+        if intermediates == b"?" {
+            match action {
+                // DECSET - DEC Private Mode Set
+                'h' => self.set_private_modes(params, true),
+                // DECRST - DEC Private Mode Reset
+                'l' => self.set_private_modes(params, false),
+                _ => unimpl(self.1),
+            }
+
+            return;
+        }
+
+        if intermediates == b">" {
+            match (action, params.iter().next()) {
+                // XTMODKEYS - Set modifyOtherKeys
+                ('m', Some(&[4])) => self.0.set_modify_other_keys(arg(params, 1, 0).min(2) as u8),
+                // Reset modifyOtherKeys
+                ('n', Some(&[4])) => self.0.set_modify_other_keys(0),
+                _ => unimpl(self.1),
+            }
+
+            return;
+        }
+
+        if !intermediates.is_empty() {
+            unimpl(self.1);
+            return;
+        }
+
+        match action {
+            // SM - Set Mode
+            'h' => self.set_modes(params, true),
+            // RM - Reset Mode
+            'l' => self.set_modes(params, false),
+            // CUU - Cursor Up
+            'A' => self.0.move_up(Row(arg(params, 0, 1))),
+            // CUD - Cursor Down, VPR - Vertical Position Relative
+            'B' | 'e' => self.0.move_down(Row(arg(params, 0, 1))),
+            // CUF - Cursor Forward, HPR - Horizontal Position Relative
+            'C' | 'a' => self.0.move_forward(Col(arg(params, 0, 1))),
+            // CUB - Cursor Backward
+            'D' => self.0.move_backward(Col(arg(params, 0, 1))),
+            // CNL - Cursor Next Line
+            'E' => {
+                self.0.move_down(Row(arg(params, 0, 1)));
+                self.0.carriage_return();
+            }
+            // CPL - Cursor Previous Line
+            'F' => {
+                self.0.move_up(Row(arg(params, 0, 1)));
+                self.0.carriage_return();
+            }
+            // CHA - Cursor Horizontal Absolute, HPA - Horizontal Position Absolute
+            'G' | '`' => self.0.goto_col(Col(arg(params, 0, 1) - 1)),
+            // CUP - Cursor Position, HVP - Horizontal and Vertical Position
+            'H' | 'f' => {
+                let row = Row(arg(params, 0, 1) - 1);
+                let col = Col(arg(params, 1, 1) - 1);
+                self.0.goto(row, col);
+            }
+            // CHT - Cursor Horizontal Forward Tabulation
+            'I' => self.0.tab(arg(params, 0, 1)),
+            // CBT - Cursor Backward Tabulation
+            'Z' => self.0.back_tab(arg(params, 0, 1)),
+            // TBC - Tab Clear
+            'g' => match arg(params, 0, 0) {
+                0 => self.0.clear_tab_stop(),
+                3 => self.0.clear_all_tab_stops(),
+                _ => unimpl(self.1),
+            },
+            // ED - Erase in Display
+            'J' => match arg(params, 0, 0) {
+                0 => self.0.erase_display(Erase::ToEnd),
+                1 => self.0.erase_display(Erase::ToStart),
+                2 => self.0.erase_display(Erase::All),
+                3 => self.0.clear_history(),
+                _ => unimpl(self.1),
+            },
+            // EL - Erase in Line
+            'K' => match arg(params, 0, 0) {
+                0 => self.0.erase_line(Erase::ToEnd),
+                1 => self.0.erase_line(Erase::ToStart),
+                2 => self.0.erase_line(Erase::All),
+                _ => unimpl(self.1),
+            },
+            // IL - Insert Line
+            'L' => self.0.insert_lines(Row(arg(params, 0, 1))),
+            // DL - Delete Line
+            'M' => self.0.delete_lines(Row(arg(params, 0, 1))),
+            // ICH - Insert Character
+            '@' => self.0.insert_chars(Col(arg(params, 0, 1))),
+            // DCH - Delete Character
+            'P' => self.0.delete_chars(Col(arg(params, 0, 1))),
+            // SU - Scroll Up
+            'S' => self.0.scroll_up(Row(arg(params, 0, 1))),
+            // SD - Scroll Down
+            'T' => self.0.scroll_down(Row(arg(params, 0, 1))),
+            // SCOSC - Save Cursor
+            's' => self.0.save_cursor(),
+            // SCORC - Restore Cursor
+            'u' => self.0.restore_cursor(),
+            // DECSTBM - Set Top and Bottom Margins
+            'r' => {
+                let top = Row(arg(params, 0, 1) - 1);
+                let bottom = Row(arg(params, 1, self.0.rows().0));
+                self.0.set_scroll_region(top, bottom);
+            }
+            // ECH - Erase Character
+            'X' => self.0.erase_chars(Col(arg(params, 0, 1))),
+            // VPA - Vertical Position Absolute
+            'd' => self.0.goto_row(Row(arg(params, 0, 1) - 1)),
+            // SGR - Select Graphic Rendition
+            'm' => self.select_graphic_rendition(params),
+            _ => unimpl(self.1),
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        let unimpl = |stats: &mut Stats| {
+            stats.unhandled_esc += 1;
+            debug!(
+                "unhandled esc_dispatch({:?}, {:?}, {:02x})",
+                intermediates, ignore, byte
+            );
+        };
+
+        if ignore {
+            unimpl(self.1);
+            return;
+        }
+
+        match (intermediates, byte) {
+            // IND - Index
+            ([], b'D') => self.0.linefeed(),
+            // NEL - Next Line
+            ([], b'E') => {
+                self.0.carriage_return();
+                self.0.linefeed();
+            }
+            // RI - Reverse Index
+            ([], b'M') => self.0.reverse_index(),
+            // HTS - Horizontal Tab Set
+            ([], b'H') => self.0.set_tab_stop(),
+            // DECSC - Save Cursor
+            ([], b'7') => self.0.save_cursor(),
+            // DECRC - Restore Cursor
+            ([], b'8') => self.0.restore_cursor(),
+            // ST - String Terminator, already acted on by the parser.
+            ([], b'\\') => (),
+            // DECKPAM - Keypad Application Mode
+            ([], b'=') => self.0.set_mode(Mode::APP_KEYPAD, true),
+            // DECKPNM - Keypad Numeric Mode
+            ([], b'>') => self.0.set_mode(Mode::APP_KEYPAD, false),
+            // SCS - Select Character Set
+            ([slot @ b'('..=b'+'], set) => {
+                let slot = match slot {
+                    b'(' => CharsetSlot::G0,
+                    b')' => CharsetSlot::G1,
+                    b'*' => CharsetSlot::G2,
+                    _ => CharsetSlot::G3,
+                };
+
+                let charset = match set {
+                    b'B' => Charset::Ascii,
+                    b'0' => Charset::DecSpecialGraphics,
+                    _ => return unimpl(self.1),
+                };
+
+                self.0.designate_charset(slot, charset);
+            }
+            _ => unimpl(self.1),
+        }
+    }
+}
+
+impl<'a> StatePerform<'a> {
+    fn set_modes(&mut self, params: &Params, enable: bool) {
+        for param in params {
+            match param[0] {
+                // IRM - Insert/Replace Mode
+                4 => self.0.set_mode(Mode::INSERT, enable),
+                // LNM - Linefeed/New Line Mode
+                20 => self.0.set_mode(Mode::LINEFEED_NEWLINE, enable),
+                mode => {
+                    self.1.unhandled_csi += 1;
+                    debug!("unhandled mode {} = {}", mode, enable);
+                }
+            }
+        }
+    }
+
+    fn set_private_modes(&mut self, params: &Params, enable: bool) {
+        for param in params {
+            match param[0] {
+                // DECCKM - Cursor Keys Mode
+                1 => self.0.set_mode(Mode::APP_CURSOR, enable),
+                // DECOM - Origin Mode
+                6 => self.0.set_mode(Mode::ORIGIN, enable),
+                // DECAWM - Autowrap Mode
+                7 => self.0.set_mode(Mode::AUTOWRAP, enable),
+                // DECTCEM - Text Cursor Enable Mode
+                25 => self.0.cursor.visible = enable,
+                // X10 mouse reporting
+                9 => self.0.set_mouse_tracking(MouseTracking::X10, enable),
+                // Mouse tracking
+                1000 => self.0.set_mouse_tracking(MouseTracking::Normal, enable),
+                1002 => self.0.set_mouse_tracking(MouseTracking::Button, enable),
+                1003 => self.0.set_mouse_tracking(MouseTracking::Any, enable),
+                // Mouse encodings
+                1005 => self.0.set_mouse_encoding(MouseEncoding::Utf8, enable),
+                1006 => self.0.set_mouse_encoding(MouseEncoding::Sgr, enable),
+                1015 => self.0.set_mouse_encoding(MouseEncoding::Urxvt, enable),
+                // Focus reporting
+                1004 => self.0.set_mode(Mode::FOCUS_REPORT, enable),
+                // Bracketed paste
+                2004 => self.0.set_mode(Mode::BRACKETED_PASTE, enable),
+                // Alternate screen buffer
+                47 => self.0.set_alternate_screen(enable),
+                // Alternate screen buffer, cleared on exit
+                1047 => {
+                    if !enable && self.0.is_alternate_screen() {
+                        self.0.erase_display(Erase::All);
+                    }
+
+                    self.0.set_alternate_screen(enable);
+                }
+                // Save cursor and switch to a cleared alternate screen buffer
+                1049 => {
+                    if enable && !self.0.is_alternate_screen() {
+                        self.0.save_cursor();
+                        self.0.set_alternate_screen(true);
+                        self.0.erase_display(Erase::All);
+                    } else if !enable && self.0.is_alternate_screen() {
+                        self.0.set_alternate_screen(false);
+                        self.0.restore_cursor();
+                    }
+                }
+                mode => {
+                    self.1.unhandled_csi += 1;
+                    debug!("unhandled private mode {} = {}", mode, enable);
+                }
+            }
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &Params) {
+        if params.is_empty() {
+            self.0.pen = self.0.default_pen();
+            return;
+        }
+
+        let mut iter = params.iter();
+
+        while let Some(param) = iter.next() {
+            let state = &*self.0;
+            let default = state.default_pen();
+            let palette = |idx: u16| state.palette(idx as u8);
+            let mut pen = state.pen.clone();
+
+            match param {
+                [0] => pen = default,
+                [1] => pen.style.insert(CellStyle::BOLD),
+                [2] => pen.style.insert(CellStyle::DIM),
+                [3] => pen.style.insert(CellStyle::ITALIC),
+                [4, 0] => pen.style.remove(CellStyle::UNDERSCORE),
+                [4, ..] | [21] => pen.style.insert(CellStyle::UNDERSCORE),
+                [5] => pen.style.insert(CellStyle::BLINK_SLOW),
+                [6] => pen.style.insert(CellStyle::BLINK_FAST),
+                [7] => pen.style.insert(CellStyle::REVERSE),
+                [9] => pen.style.insert(CellStyle::STRIKE),
+                [22] => pen.style.remove(CellStyle::BOLD | CellStyle::DIM),
+                [23] => pen.style.remove(CellStyle::ITALIC),
+                [24] => pen.style.remove(CellStyle::UNDERSCORE),
+                [25] => pen
+                    .style
+                    .remove(CellStyle::BLINK_SLOW | CellStyle::BLINK_FAST),
+                [27] => pen.style.remove(CellStyle::REVERSE),
+                [29] => pen.style.remove(CellStyle::STRIKE),
+                [x @ 30..=37] => pen.foreground = palette(x - 30),
+                [38, ..] => match extended_color(param, &mut iter, state) {
+                    Some(color) => pen.foreground = color,
+                    None => {
+                        self.1.unhandled_csi += 1;
+                        debug!("unhandled sgr({:?})", param);
+                    }
+                },
+                [39] => pen.foreground = default.foreground,
+                [x @ 40..=47] => pen.background = palette(x - 40),
+                [48, ..] => match extended_color(param, &mut iter, state) {
+                    Some(color) => pen.background = color,
+                    None => {
+                        self.1.unhandled_csi += 1;
+                        debug!("unhandled sgr({:?})", param);
+                    }
+                },
+                [49] => pen.background = default.background,
+                [x @ 90..=97] => pen.foreground = palette(x - 90 + 8),
+                [x @ 100..=107] => pen.background = palette(x - 100 + 8),
+                _ => {
+                    self.1.unhandled_csi += 1;
+                    debug!("unhandled sgr({:?})", param);
+                }
+            }
+
+            self.0.pen = pen;
         }
     }
+}
 
-    fn hook(&mut self, a: &[i64], b: &[u8], c: bool) {
-        eprintln!("[UNIMPL] hook({:?}, {:?}, {:?})", a, b, c);
+/// Parses the color following a `38` or `48` SGR parameter.
+///
+/// Handles both the colon separated form (`38:2::r:g:b`, `38:5:n`), where the
+/// color is carried in sub-parameters, and the legacy semicolon separated form
+/// (`38;2;r;g;b`, `38;5;n`), where it is taken from the following parameters.
+/// Indexed colors are looked up in `state`'s palette.
+fn extended_color(param: &[u16], iter: &mut ParamsIter, state: &State) -> Option<Color> {
+    let byte = |x: u16| if x > 255 { None } else { Some(x as u8) };
+
+    if param.len() > 1 {
+        return match param[1..] {
+            [5, idx] => Some(state.palette(byte(idx)?)),
+            [2, r, g, b] | [2, _, r, g, b] => Some(Color::new(byte(r)?, byte(g)?, byte(b)?)),
+            _ => None,
+        };
     }
 
-    fn put(&mut self, _: u8) {
-        unimplemented!()
+    match iter.next()? {
+        [5] => Some(state.palette(byte(iter.next()?[0])?)),
+        [2] => {
+            let r = byte(iter.next()?[0])?;
+            let g = byte(iter.next()?[0])?;
+            let b = byte(iter.next()?[0])?;
+            Some(Color::new(r, g, b))
+        }
+        _ => None,
     }
+}
+
+fn parse_number(text: &[u8]) -> Option<u8> {
+    std::str::from_utf8(text).ok()?.parse().ok()
+}
+
+/// Parses a color as XParseColor does, in either the `rgb:r/g/b` form with
+/// one to four hex digits per component, or the older `#rgb` form with the
+/// same number of digits for each component.
+fn parse_color(spec: &[u8]) -> Option<Color> {
+    let spec = std::str::from_utf8(spec).ok()?;
+
+    // Scale a component with `digits` hex digits to eight bits.
+    let component = |text: &str, scaled: bool| -> Option<u8> {
+        let digits = text.len() as u32;
+
+        if digits == 0 || digits > 4 || !text.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let value = u32::from_str_radix(text, 16).ok()?;
+
+        // `rgb:` scales so that all ones is full intensity; `#` just takes
+        // the digits as the high bits.
+        let value = if scaled {
+            value * 255 / ((1 << (4 * digits)) - 1)
+        } else if digits == 1 {
+            value << 4
+        } else {
+            value >> (4 * (digits - 2))
+        };
+
+        Some(value as u8)
+    };
+
+    if let Some(rgb) = spec.strip_prefix("rgb:") {
+        let mut parts = rgb.split('/');
+        let r = component(parts.next()?, true)?;
+        let g = component(parts.next()?, true)?;
+        let b = component(parts.next()?, true)?;
+
+        if parts.next().is_some() {
+            return None;
+        }
 
-    fn unhook(&mut self) {
-        unimplemented!()
+        return Some(Color::new(r, g, b));
     }
 
-    fn osc_dispatch(&mut self, _: &[&[u8]]) {
-        unimplemented!()
+    let hex = spec.strip_prefix('#')?;
+
+    if hex.len() % 3 != 0 || !hex.is_ascii() {
+        return None;
     }
 
-    fn csi_dispatch(&mut self, _: &[i64], _: &[u8], _: bool, _: char) {
-        unimplemented!()
+    let digits = hex.len() / 3;
+    let r = component(&hex[..digits], false)?;
+    let g = component(&hex[digits..2 * digits], false)?;
+    let b = component(&hex[2 * digits..], false)?;
+
+    Some(Color::new(r, g, b))
+}
+
+/// Formats a color the way xterm reports it, with four hex digits for each
+/// component.
+fn color_spec(color: Color) -> String {
+    let wide = |c: u8| u16::from(c) * 0x101;
+
+    format!(
+        "rgb:{:04x}/{:04x}/{:04x}",
+        wide(color.r),
+        wide(color.g),
+        wide(color.b)
+    )
+}
+
+/// Takes the path from a `file://host/path` URL, decoding any percent escapes.
+/// The host is ignored, since the child is always running locally.
+fn file_url_path(url: &[u8]) -> Option<String> {
+    let rest = url.strip_prefix(b"file://")?;
+    let start = rest.iter().position(|b| *b == b'/')?;
+
+    let mut path = Vec::with_capacity(rest.len() - start);
+    let mut bytes = rest[start..].iter();
+
+    while let Some(&byte) = bytes.next() {
+        if byte != b'%' {
+            path.push(byte);
+            continue;
+        }
+
+        let hex = [*bytes.next()?, *bytes.next()?];
+        path.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
     }
 
-    fn esc_dispatch(&mut self, _: &[i64], _: &[u8], _: bool, _: u8) {
-        unimplemented!()
+    String::from_utf8(path).ok()
+}
+
+/// Returns the parameter at `idx`, or `default` if it is missing or zero.
+fn arg(params: &Params, idx: usize, default: u16) -> u16 {
+    match params.iter().nth(idx) {
+        Some(&[p, ..]) if p > 0 => p,
+        _ => default,
     }
 }
 
@@ -269,7 +1029,7 @@ mod C1 {
 #[cfg(test)]
 mod tests {
     mod state {
-        use muxr::state::{Col, Row, State};
+        use muxr_core::state::{Col, Row, State};
 
         #[test]
         fn print_basic() {
@@ -287,10 +1047,17 @@ mod tests {
             let mut state = State::with_dimensions(Row(2), Col(1));
             state.print('c');
 
+            assert_eq!(state.cursor.position, (Row(0), Col(0)));
+
+            state.print('d');
+
             assert_eq!(state.cursor.position, (Row(1), Col(0)));
 
             let cell = state.cell(Row(0), Col(0)).unwrap();
             assert_eq!(cell.content, Some('c'));
+
+            let cell = state.cell(Row(1), Col(0)).unwrap();
+            assert_eq!(cell.content, Some('d'));
         }
 
         #[test]
@@ -303,6 +1070,7 @@ mod tests {
             state.cursor.position = (Row(2), Col(0));
 
             state.print('c');
+            state.print('d');
 
             assert_eq!(state.cursor.position, (Row(2), Col(0)));
 
@@ -313,7 +1081,1116 @@ mod tests {
             assert_eq!(cell.content, Some('c'));
 
             let cell = state.cell(Row(2), Col(0)).unwrap();
+            assert_eq!(cell.content, Some('d'));
+        }
+    }
+
+    mod perform {
+        use super::super::{Clipboard, Output, StatePerform};
+
+        use muxr_core::state::{
+            CellStyle, Col, Color, Mode, MouseEncoding, MouseTracking, Row, State,
+        };
+        use muxr_core::stats::Stats;
+
+        use vte::Parser;
+
+        fn parse(state: &mut State, bytes: &[u8]) -> (Stats, Output) {
+            let mut stats = Stats::default();
+            let mut output = Output::default();
+            let mut parser = Parser::new();
+            let mut perform = StatePerform(state, &mut stats, &mut output);
+
+            for byte in bytes {
+                parser.advance(&mut perform, *byte);
+            }
+
+            (stats, output)
+        }
+
+        fn feed(state: &mut State, bytes: &[u8]) -> Stats {
+            parse(state, bytes).0
+        }
+
+        /// Like `feed`, but returns what would be written back to the child.
+        fn query(state: &mut State, bytes: &[u8]) -> String {
+            String::from_utf8(parse(state, bytes).1.replies).unwrap()
+        }
+
+        #[test]
+        fn cursor_position() {
+            let mut state = State::default();
+            feed(&mut state, b"\x1b[5;10H");
+            assert_eq!(state.cursor.position, (Row(4), Col(9)));
+
+            feed(&mut state, b"\x1b[H");
+            assert_eq!(state.cursor.position, (Row(0), Col(0)));
+        }
+
+        #[test]
+        fn cursor_position_clamped() {
+            let mut state = State::with_dimensions(Row(5), Col(5));
+            feed(&mut state, b"\x1b[99;99f");
+            assert_eq!(state.cursor.position, (Row(4), Col(4)));
+        }
+
+        #[test]
+        fn cursor_relative() {
+            let mut state = State::with_dimensions(Row(10), Col(10));
+            feed(&mut state, b"\x1b[3B\x1b[4C");
+            assert_eq!(state.cursor.position, (Row(3), Col(4)));
+
+            feed(&mut state, b"\x1b[A\x1b[2D");
+            assert_eq!(state.cursor.position, (Row(2), Col(2)));
+
+            feed(&mut state, b"\x1b[50A\x1b[50D");
+            assert_eq!(state.cursor.position, (Row(0), Col(0)));
+
+            feed(&mut state, b"\x1b[50B\x1b[50C");
+            assert_eq!(state.cursor.position, (Row(9), Col(9)));
+        }
+
+        #[test]
+        fn cursor_next_previous_line() {
+            let mut state = State::with_dimensions(Row(10), Col(10));
+            feed(&mut state, b"\x1b[5;5H\x1b[2E");
+            assert_eq!(state.cursor.position, (Row(6), Col(0)));
+
+            feed(&mut state, b"\x1b[5G\x1b[3F");
+            assert_eq!(state.cursor.position, (Row(3), Col(0)));
+        }
+
+        #[test]
+        fn cursor_absolute() {
+            let mut state = State::with_dimensions(Row(10), Col(10));
+            feed(&mut state, b"\x1b[7d\x1b[3`");
+            assert_eq!(state.cursor.position, (Row(6), Col(2)));
+
+            feed(&mut state, b"\x1b[0G");
+            assert_eq!(state.cursor.position, (Row(6), Col(0)));
+        }
+
+        #[test]
+        fn unknown_csi_does_not_panic() {
+            let mut state = State::default();
+            feed(&mut state, b"\x1b[?1049h\x1b[5q");
+            assert_eq!(state.cursor.position, (Row(0), Col(0)));
+        }
+
+        #[test]
+        fn sgr_style() {
+            let mut state = State::default();
+            feed(&mut state, b"\x1b[1;3;4mA\x1b[22;24mB\x1b[0mC");
+
+            let a = state.cell(Row(0), Col(0)).unwrap();
+            assert_eq!(
+                a.style,
+                CellStyle::BOLD | CellStyle::ITALIC | CellStyle::UNDERSCORE
+            );
+
+            let b = state.cell(Row(0), Col(1)).unwrap();
+            assert_eq!(b.style, CellStyle::ITALIC);
+
+            let c = state.cell(Row(0), Col(2)).unwrap();
+            assert_eq!(c.style, CellStyle::NORMAL);
+        }
+
+        #[test]
+        fn sgr_basic_colors() {
+            let mut state = State::default();
+            feed(&mut state, b"\x1b[31;42mA\x1b[91;104mB\x1b[39;49mC");
+
+            let a = state.cell(Row(0), Col(0)).unwrap();
+            assert_eq!(a.foreground, Color::indexed(1));
+            assert_eq!(a.background, Color::indexed(2));
+
+            let b = state.cell(Row(0), Col(1)).unwrap();
+            assert_eq!(b.foreground, Color::indexed(9));
+            assert_eq!(b.background, Color::indexed(12));
+
+            let c = state.cell(Row(0), Col(2)).unwrap();
+            assert_eq!(c.foreground, Color::WHITE);
+            assert_eq!(c.background, Color::BLACK);
+        }
+
+        #[test]
+        fn sgr_extended_colors() {
+            let mut state = State::default();
+            feed(&mut state, b"\x1b[38;5;196;48;2;1;2;3mA");
+            feed(&mut state, b"\x1b[38:2::10:20:30;48:5:232mB");
+            feed(&mut state, b"\x1b[38:2:40:50:60mC");
+
+            let a = state.cell(Row(0), Col(0)).unwrap();
+            assert_eq!(a.foreground, Color::new(255, 0, 0));
+            assert_eq!(a.background, Color::new(1, 2, 3));
+
+            let b = state.cell(Row(0), Col(1)).unwrap();
+            assert_eq!(b.foreground, Color::new(10, 20, 30));
+            assert_eq!(b.background, Color::new(8, 8, 8));
+
+            let c = state.cell(Row(0), Col(2)).unwrap();
+            assert_eq!(c.foreground, Color::new(40, 50, 60));
+        }
+
+        #[test]
+        fn sgr_malformed_color() {
+            let mut state = State::default();
+            feed(&mut state, b"\x1b[38;5m\x1b[38;2;1;2m\x1b[48;5;999mA");
+
+            let a = state.cell(Row(0), Col(0)).unwrap();
+            assert_eq!(a.foreground, Color::WHITE);
+            assert_eq!(a.background, Color::BLACK);
+        }
+
+        fn row_text(state: &State, row: Row) -> String {
+            (0..state.columns().0)
+                .map(|c| state.cell(row, Col(c)).unwrap().content.unwrap_or(' '))
+                .collect()
+        }
+
+        #[test]
+        fn erase_display() {
+            let mut state = State::with_dimensions(Row(3), Col(4));
+            feed(&mut state, b"abc\x1b[2Hdef\x1b[3Hghi\x1b[2;2H\x1b[J");
+            assert_eq!(row_text(&state, Row(0)), "abc ");
+            assert_eq!(row_text(&state, Row(1)), "d   ");
+            assert_eq!(row_text(&state, Row(2)), "    ");
+
+            let mut state = State::with_dimensions(Row(3), Col(4));
+            feed(&mut state, b"abc\x1b[2Hdef\x1b[3Hghi\x1b[2;2H\x1b[1J");
+            assert_eq!(row_text(&state, Row(0)), "    ");
+            assert_eq!(row_text(&state, Row(1)), "  f ");
+            assert_eq!(row_text(&state, Row(2)), "ghi ");
+
+            feed(&mut state, b"\x1b[2J");
+            assert_eq!(row_text(&state, Row(2)), "    ");
+            assert_eq!(state.cursor.position, (Row(1), Col(1)));
+        }
+
+        #[test]
+        fn erase_line() {
+            let mut state = State::with_dimensions(Row(1), Col(5));
+            feed(&mut state, b"abcd\x1b[3G\x1b[K");
+            assert_eq!(row_text(&state, Row(0)), "ab   ");
+
+            feed(&mut state, b"\x1b[Hxyz\x1b[2G\x1b[1K");
+            assert_eq!(row_text(&state, Row(0)), "  z  ");
+
+            feed(&mut state, b"\x1b[2K");
+            assert_eq!(row_text(&state, Row(0)), "     ");
+        }
+
+        #[test]
+        fn erase_chars() {
+            let mut state = State::with_dimensions(Row(1), Col(5));
+            feed(&mut state, b"abcd\x1b[2G\x1b[2X");
+            assert_eq!(row_text(&state, Row(0)), "a  d ");
+            assert_eq!(state.cursor.position, (Row(0), Col(1)));
+
+            feed(&mut state, b"\x1b[99X");
+            assert_eq!(row_text(&state, Row(0)), "a    ");
+        }
+
+        #[test]
+        fn erase_uses_background() {
+            let mut state = State::with_dimensions(Row(2), Col(2));
+            feed(&mut state, b"\x1b[44m\x1b[2J");
+
+            let cell = state.cell(Row(1), Col(1)).unwrap();
+            assert_eq!(cell.background, Color::indexed(4));
             assert_eq!(cell.content, None);
         }
+
+        #[test]
+        fn erase_after_scroll() {
+            let mut state = State::with_dimensions(Row(2), Col(2));
+            feed(&mut state, b"abcde");
+            assert_eq!(row_text(&state, Row(0)), "cd");
+            assert_eq!(row_text(&state, Row(1)), "e ");
+
+            feed(&mut state, b"\x1b[1;2H\x1b[J");
+            assert_eq!(row_text(&state, Row(0)), "c ");
+            assert_eq!(row_text(&state, Row(1)), "  ");
+        }
+
+        #[test]
+        fn insert_delete_lines() {
+            let mut state = State::with_dimensions(Row(4), Col(2));
+            feed(&mut state, b"a\x1b[2Hb\x1b[3Hc\x1b[4Hd");
+
+            feed(&mut state, b"\x1b[2;2H\x1b[2L");
+            assert_eq!(state.cursor.position, (Row(1), Col(0)));
+            assert_eq!(row_text(&state, Row(0)), "a ");
+            assert_eq!(row_text(&state, Row(1)), "  ");
+            assert_eq!(row_text(&state, Row(2)), "  ");
+            assert_eq!(row_text(&state, Row(3)), "b ");
+
+            feed(&mut state, b"\x1b[M");
+            assert_eq!(row_text(&state, Row(0)), "a ");
+            assert_eq!(row_text(&state, Row(1)), "  ");
+            assert_eq!(row_text(&state, Row(2)), "b ");
+            assert_eq!(row_text(&state, Row(3)), "  ");
+
+            feed(&mut state, b"\x1b[H\x1b[99M");
+            assert_eq!(row_text(&state, Row(0)), "  ");
+            assert_eq!(row_text(&state, Row(2)), "  ");
+        }
+
+        #[test]
+        fn insert_delete_chars() {
+            let mut state = State::with_dimensions(Row(1), Col(6));
+            feed(&mut state, b"abcde\x1b[2G\x1b[2@");
+            assert_eq!(row_text(&state, Row(0)), "a  bcd");
+            assert_eq!(state.cursor.position, (Row(0), Col(1)));
+
+            feed(&mut state, b"\x1b[3P");
+            assert_eq!(row_text(&state, Row(0)), "acd   ");
+
+            feed(&mut state, b"\x1b[99@");
+            assert_eq!(row_text(&state, Row(0)), "a     ");
+        }
+
+        #[test]
+        fn linefeed_scrolls_at_bottom() {
+            let mut state = State::with_dimensions(Row(2), Col(2));
+            feed(&mut state, b"a\r\nb\r\nc");
+            assert_eq!(state.cursor.position, (Row(1), Col(1)));
+            assert_eq!(row_text(&state, Row(0)), "b ");
+            assert_eq!(row_text(&state, Row(1)), "c ");
+        }
+
+        #[test]
+        fn scroll_region() {
+            let mut state = State::with_dimensions(Row(4), Col(2));
+            feed(&mut state, b"a\x1b[2Hb\x1b[3Hc\x1b[4Hd");
+
+            feed(&mut state, b"\x1b[2;3r");
+            assert_eq!(state.scroll_region(), (Row(1), Row(3)));
+            assert_eq!(state.cursor.position, (Row(0), Col(0)));
+
+            feed(&mut state, b"\x1b[3H\n");
+            assert_eq!(state.cursor.position, (Row(2), Col(0)));
+            assert_eq!(row_text(&state, Row(0)), "a ");
+            assert_eq!(row_text(&state, Row(1)), "c ");
+            assert_eq!(row_text(&state, Row(2)), "  ");
+            assert_eq!(row_text(&state, Row(3)), "d ");
+
+            feed(&mut state, b"\x1b[2H\x1bM");
+            assert_eq!(row_text(&state, Row(1)), "  ");
+            assert_eq!(row_text(&state, Row(2)), "c ");
+            assert_eq!(row_text(&state, Row(3)), "d ");
+
+            feed(&mut state, b"\x1b[r");
+            assert_eq!(state.scroll_region(), (Row(0), Row(4)));
+        }
+
+        #[test]
+        fn scroll_region_invalid() {
+            let mut state = State::with_dimensions(Row(4), Col(1));
+            feed(&mut state, b"\x1b[3;3r\x1b[4;2r");
+            assert_eq!(state.scroll_region(), (Row(0), Row(4)));
+        }
+
+        #[test]
+        fn scroll_up_down() {
+            let mut state = State::with_dimensions(Row(3), Col(2));
+            feed(&mut state, b"a\x1b[2Hb\x1b[3Hc");
+
+            feed(&mut state, b"\x1b[S");
+            assert_eq!(row_text(&state, Row(0)), "b ");
+            assert_eq!(row_text(&state, Row(1)), "c ");
+            assert_eq!(row_text(&state, Row(2)), "  ");
+
+            feed(&mut state, b"\x1b[2T");
+            assert_eq!(row_text(&state, Row(0)), "  ");
+            assert_eq!(row_text(&state, Row(1)), "  ");
+            assert_eq!(row_text(&state, Row(2)), "b ");
+        }
+
+        #[test]
+        fn index_and_next_line() {
+            let mut state = State::with_dimensions(Row(3), Col(3));
+            feed(&mut state, b"\x1b[1;2H\x1bD");
+            assert_eq!(state.cursor.position, (Row(1), Col(1)));
+
+            feed(&mut state, b"\x1bE");
+            assert_eq!(state.cursor.position, (Row(2), Col(0)));
+
+            feed(&mut state, &[0x85, 0x8d]);
+            assert_eq!(state.cursor.position, (Row(1), Col(0)));
+        }
+
+        #[test]
+        fn cursor_stops_at_margins() {
+            let mut state = State::with_dimensions(Row(5), Col(1));
+            feed(&mut state, b"\x1b[2;4r\x1b[3H\x1b[9A");
+            assert_eq!(state.cursor.position, (Row(1), Col(0)));
+
+            feed(&mut state, b"\x1b[9B");
+            assert_eq!(state.cursor.position, (Row(3), Col(0)));
+
+            feed(&mut state, b"\x1b[5H\x1b[9B");
+            assert_eq!(state.cursor.position, (Row(4), Col(0)));
+
+            feed(&mut state, b"\x1b[1H\x1b[9A");
+            assert_eq!(state.cursor.position, (Row(0), Col(0)));
+        }
+
+        #[test]
+        fn alternate_screen_1049() {
+            let mut state = State::with_dimensions(Row(2), Col(3));
+            feed(&mut state, b"ab\x1b[31m");
+
+            feed(&mut state, b"\x1b[?1049h");
+            assert!(state.is_alternate_screen());
+            assert_eq!(row_text(&state, Row(0)), "   ");
+            assert_eq!(state.cursor.position, (Row(0), Col(2)));
+
+            feed(&mut state, b"\x1b[0m\x1b[Hxyz");
+            assert_eq!(row_text(&state, Row(0)), "xyz");
+
+            feed(&mut state, b"\x1b[?1049l");
+            assert!(!state.is_alternate_screen());
+            assert_eq!(row_text(&state, Row(0)), "ab ");
+            assert_eq!(state.cursor.position, (Row(0), Col(2)));
+            assert_eq!(state.pen.foreground, Color::indexed(1));
+        }
+
+        #[test]
+        fn alternate_screen_47_keeps_contents() {
+            let mut state = State::with_dimensions(Row(2), Col(3));
+            feed(&mut state, b"\x1b[?47hab\x1b[?47l");
+            assert_eq!(row_text(&state, Row(0)), "   ");
+            assert_eq!(state.cursor.position, (Row(0), Col(2)));
+
+            feed(&mut state, b"\x1b[?47h");
+            assert_eq!(row_text(&state, Row(0)), "ab ");
+
+            feed(&mut state, b"\x1b[?1047l\x1b[?1047h");
+            assert_eq!(row_text(&state, Row(0)), "   ");
+        }
+
+        #[test]
+        fn autowrap() {
+            let mut state = State::with_dimensions(Row(2), Col(3));
+            feed(&mut state, b"abc");
+            assert_eq!(state.cursor.position, (Row(0), Col(2)));
+
+            feed(&mut state, b"d");
+            assert_eq!(state.cursor.position, (Row(1), Col(1)));
+            assert_eq!(row_text(&state, Row(0)), "abc");
+            assert_eq!(row_text(&state, Row(1)), "d  ");
+        }
+
+        #[test]
+        fn autowrap_cancelled_by_movement() {
+            let mut state = State::with_dimensions(Row(2), Col(3));
+            feed(&mut state, b"abc\rx");
+            assert_eq!(row_text(&state, Row(0)), "xbc");
+            assert_eq!(row_text(&state, Row(1)), "   ");
+        }
+
+        #[test]
+        fn autowrap_disabled() {
+            let mut state = State::with_dimensions(Row(2), Col(3));
+            feed(&mut state, b"\x1b[?7labcde");
+            assert_eq!(state.cursor.position, (Row(0), Col(2)));
+            assert_eq!(row_text(&state, Row(0)), "abe");
+            assert_eq!(row_text(&state, Row(1)), "   ");
+        }
+
+        #[test]
+        fn insert_mode() {
+            let mut state = State::with_dimensions(Row(1), Col(4));
+            feed(&mut state, b"abc\x1b[H\x1b[4hxy\x1b[4lz");
+            assert_eq!(row_text(&state, Row(0)), "xyzb");
+        }
+
+        #[test]
+        fn linefeed_newline_mode() {
+            let mut state = State::with_dimensions(Row(3), Col(3));
+            feed(&mut state, b"a\nb");
+            assert_eq!(state.cursor.position, (Row(1), Col(2)));
+
+            feed(&mut state, b"\x1b[20hc\nd");
+            assert_eq!(state.cursor.position, (Row(2), Col(1)));
+        }
+
+        #[test]
+        fn origin_mode() {
+            let mut state = State::with_dimensions(Row(5), Col(3));
+            feed(&mut state, b"\x1b[2;4r\x1b[?6h");
+            assert_eq!(state.cursor.position, (Row(1), Col(0)));
+
+            feed(&mut state, b"\x1b[2;2H");
+            assert_eq!(state.cursor.position, (Row(2), Col(1)));
+
+            feed(&mut state, b"\x1b[9;1H");
+            assert_eq!(state.cursor.position, (Row(3), Col(0)));
+
+            feed(&mut state, b"\x1b[?6l");
+            assert_eq!(state.cursor.position, (Row(0), Col(0)));
+        }
+
+        #[test]
+        fn flag_modes() {
+            let mut state = State::default();
+            assert!(state.cursor.visible);
+            assert_eq!(state.modes(), Mode::AUTOWRAP);
+
+            feed(&mut state, b"\x1b[?25l\x1b[?1h\x1b=");
+            assert!(!state.cursor.visible);
+            assert!(state.modes().contains(Mode::APP_CURSOR | Mode::APP_KEYPAD));
+
+            feed(&mut state, b"\x1b[?25h\x1b[?1l\x1b>");
+            assert!(state.cursor.visible);
+            assert_eq!(state.modes(), Mode::AUTOWRAP);
+
+            feed(&mut state, b"\x1b[?2004h");
+            assert!(state.modes().contains(Mode::BRACKETED_PASTE));
+
+            feed(&mut state, b"\x1b[?2004l");
+            assert_eq!(state.modes(), Mode::AUTOWRAP);
+
+            feed(&mut state, b"\x1b[?1004h");
+            assert!(state.modes().contains(Mode::FOCUS_REPORT));
+
+            feed(&mut state, b"\x1b[?1004l");
+            assert_eq!(state.modes(), Mode::AUTOWRAP);
+        }
+
+        #[test]
+        fn mouse_modes() {
+            let mut state = State::default();
+            assert_eq!(state.mouse_tracking(), MouseTracking::Off);
+
+            feed(&mut state, b"\x1b[?1000h\x1b[?1006h");
+            assert_eq!(state.mouse_tracking(), MouseTracking::Normal);
+            assert_eq!(state.mouse_encoding(), MouseEncoding::Sgr);
+
+            // Resetting a mode that isn't in use changes nothing.
+            feed(&mut state, b"\x1b[?1003h\x1b[?1002l\x1b[?1015l");
+            assert_eq!(state.mouse_tracking(), MouseTracking::Any);
+            assert_eq!(state.mouse_encoding(), MouseEncoding::Sgr);
+
+            feed(&mut state, b"\x1b[?1003l\x1b[?1006l");
+            assert_eq!(state.mouse_tracking(), MouseTracking::Off);
+            assert_eq!(state.mouse_encoding(), MouseEncoding::X10);
+        }
+
+        #[test]
+        fn modify_other_keys() {
+            let mut state = State::default();
+
+            feed(&mut state, b"\x1b[>4;2m");
+            assert_eq!(state.modify_other_keys(), 2);
+
+            feed(&mut state, b"\x1b[>4;9m");
+            assert_eq!(state.modify_other_keys(), 2);
+
+            feed(&mut state, b"\x1b[>4m");
+            assert_eq!(state.modify_other_keys(), 0);
+
+            feed(&mut state, b"\x1b[>4;1m\x1b[>4n");
+            assert_eq!(state.modify_other_keys(), 0);
+        }
+
+        #[test]
+        fn save_restore_cursor() {
+            let mut state = State::with_dimensions(Row(5), Col(5));
+            feed(
+                &mut state,
+                b"\x1b[2;4r\x1b[?6h\x1b[2;3H\x1b[1;32m\x1b(0\x1b7",
+            );
+            assert_eq!(state.cursor.position, (Row(2), Col(2)));
+
+            feed(&mut state, b"\x1b[0m\x1b(B\x1b[?6l\x1b[5;5H");
+            assert_eq!(state.cursor.position, (Row(4), Col(4)));
+
+            feed(&mut state, b"\x1b8");
+            assert_eq!(state.cursor.position, (Row(2), Col(2)));
+            assert!(state.modes().contains(Mode::ORIGIN));
+            assert_eq!(state.pen.style, CellStyle::BOLD);
+            assert_eq!(state.pen.foreground, Color::indexed(2));
+
+            feed(&mut state, b"q");
+            assert_eq!(row_text(&state, Row(2)), "  ─  ");
+        }
+
+        #[test]
+        fn save_restore_pending_wrap() {
+            let mut state = State::with_dimensions(Row(2), Col(5));
+            feed(&mut state, b"abcde\x1b7\x1b[H\x1b8f");
+
+            assert_eq!(row_text(&state, Row(0)), "abcde");
+            assert_eq!(row_text(&state, Row(1)), "f    ");
+        }
+
+        #[test]
+        fn save_restore_cursor_csi() {
+            let mut state = State::with_dimensions(Row(5), Col(5));
+            feed(&mut state, b"\x1b[3;4H\x1b[s\x1b[H\x1b[u");
+            assert_eq!(state.cursor.position, (Row(2), Col(3)));
+        }
+
+        #[test]
+        fn restore_cursor_without_save() {
+            let mut state = State::with_dimensions(Row(5), Col(5));
+            feed(&mut state, b"\x1b[31m\x1b[3;4H\x1b8");
+            assert_eq!(state.cursor.position, (Row(0), Col(0)));
+            assert_eq!(state.pen.foreground, Color::WHITE);
+        }
+
+        #[test]
+        fn restore_cursor_without_save_uses_default_colors() {
+            let mut state = State::with_dimensions(Row(5), Col(5));
+            feed(
+                &mut state,
+                b"\x1b]10;#123456\x07\x1b]11;#abcdef\x07\x1b[31m\x1b8",
+            );
+            assert_eq!(state.pen.foreground, Color::new(0x12, 0x34, 0x56));
+            assert_eq!(state.pen.background, Color::new(0xab, 0xcd, 0xef));
+        }
+
+        #[test]
+        fn save_cursor_per_screen() {
+            let mut state = State::with_dimensions(Row(5), Col(5));
+            feed(
+                &mut state,
+                b"\x1b[2;2H\x1b7\x1b[?47h\x1b[4;4H\x1b7\x1b[?47l",
+            );
+
+            feed(&mut state, b"\x1b8");
+            assert_eq!(state.cursor.position, (Row(1), Col(1)));
+
+            feed(&mut state, b"\x1b[?47h\x1b8");
+            assert_eq!(state.cursor.position, (Row(3), Col(3)));
+        }
+
+        #[test]
+        fn dec_special_graphics() {
+            let mut state = State::with_dimensions(Row(1), Col(6));
+            feed(&mut state, b"\x1b(0lqk\x1b(Bqx");
+            assert_eq!(row_text(&state, Row(0)), "┌─┐qx ");
+        }
+
+        #[test]
+        fn tab_default_stops() {
+            let mut state = State::with_dimensions(Row(1), Col(20));
+            feed(&mut state, b"a\tb\tc\td");
+            assert_eq!(row_text(&state, Row(0)), "a       b       c  d");
+        }
+
+        #[test]
+        fn tab_set_and_clear() {
+            let mut state = State::with_dimensions(Row(1), Col(20));
+            feed(&mut state, b"\x1b[3g\x1b[4G\x1bH\x1b[11G\x88\x1b[H");
+
+            feed(&mut state, b"\t");
+            assert_eq!(state.cursor.position, (Row(0), Col(3)));
+
+            feed(&mut state, b"\t");
+            assert_eq!(state.cursor.position, (Row(0), Col(10)));
+
+            feed(&mut state, b"\x1b[g\x1b[H\x1b[2I");
+            assert_eq!(state.cursor.position, (Row(0), Col(19)));
+        }
+
+        #[test]
+        fn tab_backward() {
+            let mut state = State::with_dimensions(Row(1), Col(30));
+            feed(&mut state, b"\x1b[20G\x1b[Z");
+            assert_eq!(state.cursor.position, (Row(0), Col(16)));
+
+            feed(&mut state, b"\x1b[Z");
+            assert_eq!(state.cursor.position, (Row(0), Col(8)));
+
+            feed(&mut state, b"\x1b[5Z");
+            assert_eq!(state.cursor.position, (Row(0), Col(0)));
+        }
+
+        #[test]
+        fn backspace() {
+            let mut state = State::with_dimensions(Row(2), Col(3));
+            feed(&mut state, b"ab\x08\x08\x08x");
+            assert_eq!(row_text(&state, Row(0)), "xb ");
+
+            feed(&mut state, b"\r\n\x08");
+            assert_eq!(state.cursor.position, (Row(1), Col(0)));
+        }
+
+        #[test]
+        fn bell() {
+            let mut state = State::default();
+            assert_eq!(state.bell_count(), 0);
+
+            feed(&mut state, b"\x07a\x07");
+            assert_eq!(state.bell_count(), 2);
+        }
+
+        #[test]
+        fn shift_in_out() {
+            let mut state = State::with_dimensions(Row(1), Col(5));
+            feed(&mut state, b"\x1b)0q\x0eq\x0fq");
+            assert_eq!(row_text(&state, Row(0)), "q─q  ");
+        }
+
+        #[test]
+        fn ignored_controls() {
+            let mut state = State::with_dimensions(Row(1), Col(5));
+            feed(&mut state, b"a\x00\x7fb\x1b[3\x18c\x1b[\x1ad");
+            assert_eq!(row_text(&state, Row(0)), "abcd ");
+        }
+
+        #[test]
+        fn resize_keeps_content() {
+            let mut state = State::with_dimensions(Row(3), Col(4));
+            feed(&mut state, b"abcd\r\nefgh\r\nij");
+
+            state.resize(Row(4), Col(6));
+            assert_eq!(row_text(&state, Row(0)), "abcd  ");
+            assert_eq!(row_text(&state, Row(1)), "efgh  ");
+            assert_eq!(row_text(&state, Row(2)), "ij    ");
+            assert_eq!(row_text(&state, Row(3)), "      ");
+            assert_eq!(state.cursor.position, (Row(2), Col(2)));
+
+            state.resize(Row(5), Col(2));
+            assert_eq!(row_text(&state, Row(0)), "ab");
+            assert_eq!(row_text(&state, Row(1)), "cd");
+            assert_eq!(row_text(&state, Row(2)), "ef");
+            assert_eq!(row_text(&state, Row(3)), "gh");
+            assert_eq!(row_text(&state, Row(4)), "ij");
+            assert_eq!(state.cursor.position, (Row(4), Col(1)));
+        }
+
+        #[test]
+        fn reflow_soft_wrapped_lines() {
+            let mut state = State::with_dimensions(Row(4), Col(4));
+            feed(&mut state, b"abcdefg\r\nhi");
+            assert!(state.is_wrapped(Row(0)));
+            assert!(!state.is_wrapped(Row(1)));
+
+            state.resize(Row(4), Col(8));
+            assert_eq!(row_text(&state, Row(0)), "abcdefg ");
+            assert_eq!(row_text(&state, Row(1)), "hi      ");
+            assert!(!state.is_wrapped(Row(0)));
+            assert_eq!(state.cursor.position, (Row(1), Col(2)));
+
+            state.resize(Row(4), Col(3));
+            assert_eq!(row_text(&state, Row(0)), "abc");
+            assert_eq!(row_text(&state, Row(1)), "def");
+            assert_eq!(row_text(&state, Row(2)), "g  ");
+            assert_eq!(row_text(&state, Row(3)), "hi ");
+            assert!(state.is_wrapped(Row(0)));
+            assert!(state.is_wrapped(Row(1)));
+            assert!(!state.is_wrapped(Row(2)));
+            assert_eq!(state.cursor.position, (Row(3), Col(2)));
+        }
+
+        #[test]
+        fn reflow_keeps_cursor_on_its_character() {
+            let mut state = State::with_dimensions(Row(3), Col(4));
+            feed(&mut state, b"abcdef\x1b[1;2H");
+
+            state.resize(Row(3), Col(2));
+            assert_eq!(state.cursor.position, (Row(0), Col(1)));
+
+            feed(&mut state, b"\x1b[2;2H");
+            state.resize(Row(3), Col(6));
+            assert_eq!(state.cursor.position, (Row(0), Col(3)));
+
+            // Shrinking pushes lines off the top to keep the cursor visible.
+            feed(&mut state, b"\x1b[1;6H");
+            state.resize(Row(2), Col(1));
+            assert_eq!(row_text(&state, Row(0)), "e");
+            assert_eq!(row_text(&state, Row(1)), "f");
+            assert_eq!(state.cursor.position, (Row(1), Col(0)));
+        }
+
+        #[test]
+        fn reflow_keeps_pending_wrap() {
+            let mut state = State::with_dimensions(Row(2), Col(4));
+            feed(&mut state, b"abcd");
+
+            state.resize(Row(2), Col(2));
+            assert_eq!(row_text(&state, Row(1)), "cd");
+            assert_eq!(state.cursor.position, (Row(1), Col(1)));
+
+            feed(&mut state, b"e");
+            assert_eq!(row_text(&state, Row(0)), "cd");
+            assert_eq!(row_text(&state, Row(1)), "e ");
+
+            // "ab" comes back from the scrollback.
+            state.resize(Row(2), Col(6));
+            assert_eq!(row_text(&state, Row(0)), "abcde ");
+            assert_eq!(state.cursor.position, (Row(0), Col(5)));
+            assert!(state.history().is_empty());
+        }
+
+        #[test]
+        fn erase_breaks_wrapped_line() {
+            let mut state = State::with_dimensions(Row(3), Col(3));
+            feed(&mut state, b"abcd\x1b[A\x1b[K");
+            assert!(!state.is_wrapped(Row(0)));
+
+            state.resize(Row(3), Col(6));
+            assert_eq!(row_text(&state, Row(1)), "d     ");
+        }
+
+        #[test]
+        fn resize_keeps_cursor_on_screen() {
+            let mut state = State::with_dimensions(Row(4), Col(2));
+            feed(&mut state, b"a\r\nb\r\nc\r\nd\r\ne");
+            assert_eq!(row_text(&state, Row(0)), "b ");
+
+            state.resize(Row(2), Col(2));
+            assert_eq!(row_text(&state, Row(0)), "d ");
+            assert_eq!(row_text(&state, Row(1)), "e ");
+            assert_eq!(state.cursor.position, (Row(1), Col(1)));
+
+            // With the cursor near the top, lines come off the bottom.
+            let mut state = State::with_dimensions(Row(4), Col(2));
+            feed(&mut state, b"a\r\nb\r\nc\x1b[H");
+
+            state.resize(Row(2), Col(2));
+            assert_eq!(row_text(&state, Row(0)), "a ");
+            assert_eq!(row_text(&state, Row(1)), "b ");
+            assert_eq!(state.cursor.position, (Row(0), Col(0)));
+        }
+
+        #[test]
+        fn resize_resets_region_and_extends_tabs() {
+            let mut state = State::with_dimensions(Row(4), Col(10));
+            feed(&mut state, b"\x1b[2;3r\x1b[3g");
+
+            state.resize(Row(5), Col(20));
+            assert_eq!(state.scroll_region(), (Row(0), Row(5)));
+
+            feed(&mut state, b"\t");
+            assert_eq!(state.cursor.position, (Row(0), Col(16)));
+        }
+
+        #[test]
+        fn resize_alternate_screen() {
+            let mut state = State::with_dimensions(Row(3), Col(3));
+            feed(&mut state, b"abc\r\n\r\nx\x1b[?1049h\x1b[Hy");
+
+            state.resize(Row(2), Col(2));
+            feed(&mut state, b"\x1b[?1049l");
+
+            assert_eq!(row_text(&state, Row(0)), "  ");
+            assert_eq!(row_text(&state, Row(1)), "x ");
+            assert_eq!(state.cursor.position, (Row(1), Col(1)));
+        }
+
+        #[test]
+        fn resize_alternate_screen_clears_pending_wrap() {
+            let mut state = State::with_dimensions(Row(2), Col(3));
+            feed(&mut state, b"\x1b[?1049habc");
+
+            state.resize(Row(2), Col(5));
+            feed(&mut state, b"d");
+
+            assert_eq!(row_text(&state, Row(0)), "abd  ");
+            assert_eq!(row_text(&state, Row(1)), "     ");
+        }
+
+        fn history_text(state: &State) -> Vec<String> {
+            let history = state.history();
+            history
+                .lines(history.range())
+                .map(|l| l.text().to_owned())
+                .collect()
+        }
+
+        #[test]
+        fn history_receives_scrolled_lines() {
+            let mut state = State::with_dimensions(Row(2), Col(4));
+            feed(&mut state, b"one\r\ntwo\r\nthree\r\nfour");
+
+            assert_eq!(history_text(&state), ["one", "two", "thre"]);
+            assert_eq!(state.history().range(), 0..3);
+            assert_eq!(row_text(&state, Row(0)), "e   ");
+            assert!(!state.history().get(1).unwrap().is_wrapped());
+            assert!(state.history().get(2).unwrap().is_wrapped());
+        }
+
+        #[test]
+        fn history_ignores_alternate_screen_and_regions() {
+            let mut state = State::with_dimensions(Row(3), Col(4));
+            feed(&mut state, b"\x1b[?1049ha\r\nb\r\nc\r\nd\x1b[?1049l");
+            assert!(state.history().is_empty());
+
+            feed(&mut state, b"\x1b[1;2ra\r\nb\r\nc");
+            assert!(state.history().is_empty());
+        }
+
+        #[test]
+        fn history_limit() {
+            let mut state = State::with_dimensions(Row(1), Col(4));
+            state.set_history_limit(2);
+            feed(&mut state, b"a\r\nb\r\nc\r\nd\r\ne");
+
+            assert_eq!(history_text(&state), ["c", "d"]);
+            assert_eq!(state.history().range(), 2..4);
+            assert_eq!(state.history().get(3).unwrap().text(), "d");
+            assert!(state.history().get(1).is_none());
+            assert!(state.history().get(4).is_none());
+
+            let lines: Vec<_> = state.history().lines(0..3).map(|l| l.text()).collect();
+            assert_eq!(lines, ["c"]);
+
+            state.set_history_limit(1);
+            assert_eq!(history_text(&state), ["d"]);
+        }
+
+        #[test]
+        fn history_keeps_attributes() {
+            let mut state = State::with_dimensions(Row(1), Col(6));
+            feed(&mut state, b"a\x1b[1mbc\x1b[0m\x1b[Cd\x1b[44m \x1b[0m\r\n");
+
+            let line = state.history().get(0).unwrap();
+            assert_eq!(line.text(), "abc d ");
+
+            let cells: Vec<_> = line.cells().collect();
+            assert_eq!(cells.len(), 6);
+            assert!(!cells[0].style.contains(CellStyle::BOLD));
+            assert!(cells[1].style.contains(CellStyle::BOLD));
+            assert!(cells[2].style.contains(CellStyle::BOLD));
+            assert_eq!(cells[3].content, None);
+            assert_eq!(cells[5].content, Some(' '));
+            assert_eq!(cells[5].background, Color::indexed(4));
+        }
+
+        #[test]
+        fn erase_saved_lines() {
+            let mut state = State::with_dimensions(Row(1), Col(4));
+            feed(&mut state, b"a\r\nb\r\nc");
+            assert_eq!(state.history().range(), 0..2);
+
+            feed(&mut state, b"\x1b[3J");
+            assert!(state.history().is_empty());
+            assert_eq!(state.history().range(), 2..2);
+            assert_eq!(row_text(&state, Row(0)), "c   ");
+        }
+
+        #[test]
+        fn resize_moves_lines_through_history() {
+            let mut state = State::with_dimensions(Row(3), Col(4));
+            feed(&mut state, b"a\r\nb\r\nc");
+
+            state.resize(Row(2), Col(4));
+            assert_eq!(history_text(&state), ["a"]);
+            assert_eq!(row_text(&state, Row(0)), "b   ");
+
+            state.resize(Row(4), Col(4));
+            assert!(state.history().is_empty());
+            assert_eq!(row_text(&state, Row(0)), "a   ");
+            assert_eq!(state.cursor.position, (Row(2), Col(1)));
+        }
+
+        #[test]
+        fn resize_keeps_older_line_numbers() {
+            let mut state = State::with_dimensions(Row(2), Col(4));
+            for i in 0..10 {
+                feed(&mut state, format!("l{}\r\n", i).as_bytes());
+            }
+            feed(&mut state, b"abcdef");
+            assert_eq!(state.history().range(), 0..10);
+
+            // Only the end of the history is re-wrapped, so the lines before
+            // it keep their numbers through any number of resizes.
+            for cols in &[3, 6, 1, 4] {
+                state.resize(Row(2), Col(*cols));
+
+                let line = state.history().get(3).unwrap();
+                assert_eq!(line.text(), "l3");
+            }
+        }
+
+        #[test]
+        fn title_and_icon_name() {
+            let mut state = State::default();
+            feed(&mut state, b"\x1b]0;a;b\x07");
+            assert_eq!(state.title(), "a;b");
+            assert_eq!(state.icon_name(), "a;b");
+
+            feed(&mut state, b"\x1b]2;vim\x1b\\\x1b]1;icon\x07");
+            assert_eq!(state.title(), "vim");
+            assert_eq!(state.icon_name(), "icon");
+        }
+
+        #[test]
+        fn working_directory() {
+            let mut state = State::default();
+            feed(&mut state, b"\x1b]7;file://host/home/a%20b\x07");
+            assert_eq!(state.working_directory(), Some("/home/a b"));
+
+            let stats = feed(&mut state, b"\x1b]7;http://host/x\x07");
+            assert_eq!(stats.unhandled_osc, 1);
+            assert_eq!(state.working_directory(), Some("/home/a b"));
+        }
+
+        #[test]
+        fn palette_set_query_and_reset() {
+            let mut state = State::default();
+            feed(&mut state, b"\x1b]4;1;rgb:ff/80/0;2;#123\x07\x1b[31m");
+            assert_eq!(state.palette(1), Color::new(0xff, 0x80, 0));
+            assert_eq!(state.palette(2), Color::new(0x10, 0x20, 0x30));
+            assert_eq!(state.pen.foreground, Color::new(0xff, 0x80, 0));
+
+            let reply = query(&mut state, b"\x1b]4;1;?;3;?\x1b\\");
+            assert_eq!(
+                reply,
+                "\x1b]4;1;rgb:ffff/8080/0000\x1b\\\x1b]4;3;rgb:cdcd/cdcd/0000\x1b\\"
+            );
+
+            feed(&mut state, b"\x1b]104;1\x07");
+            assert_eq!(state.palette(1), Color::indexed(1));
+            assert_eq!(state.palette(2), Color::new(0x10, 0x20, 0x30));
+
+            feed(&mut state, b"\x1b]104\x07");
+            assert_eq!(state.palette(2), Color::indexed(2));
+        }
+
+        #[test]
+        fn default_and_cursor_colors() {
+            let mut state = State::default();
+            feed(
+                &mut state,
+                b"\x1b]10;rgb:1/2/3;#abcdef\x07\x1b]12;#fff\x07\x1b[m",
+            );
+            assert_eq!(state.default_foreground(), Color::new(0x11, 0x22, 0x33));
+            assert_eq!(state.default_background(), Color::new(0xab, 0xcd, 0xef));
+            assert_eq!(state.cursor.color, Color::new(0xf0, 0xf0, 0xf0));
+            assert_eq!(state.pen.foreground, Color::new(0x11, 0x22, 0x33));
+
+            let reply = query(&mut state, b"\x1b]11;?\x07");
+            assert_eq!(reply, "\x1b]11;rgb:abab/cdcd/efef\x07");
+
+            feed(&mut state, b"\x1b]110\x07\x1b]111\x07\x1b]112\x07\x1b[39m");
+            assert_eq!(state.default_foreground(), Color::WHITE);
+            assert_eq!(state.default_background(), Color::BLACK);
+            assert_eq!(state.cursor.color, Color::WHITE);
+            assert_eq!(state.pen.foreground, Color::WHITE);
+        }
+
+        #[test]
+        fn clipboard() {
+            let mut state = State::default();
+            let (stats, output) = parse(
+                &mut state,
+                b"\x1b]52;c;aGVsbG8=\x07\x1b]52;;?\x1b\\\x1b]52;c;!!\x07",
+            );
+
+            let set = Clipboard::Set {
+                selection: "c".into(),
+                data: "hello".into(),
+            };
+            let query = Clipboard::Query {
+                selection: "s0".into(),
+                terminator: "\x1b\\",
+            };
+
+            assert_eq!(output.clipboard, vec![set, query]);
+            assert_eq!(stats.unhandled_osc, 1);
+        }
+
+        #[test]
+        fn unhandled_sequences_are_counted() {
+            let mut state = State::with_dimensions(Row(2), Col(5));
+            let stats = feed(
+                &mut state,
+                b"\x1b[5y\x1b[?9999h\x1b[99m\x1b#8\x1b]999;x\x07\x1bPq#0\x1b\\\x86",
+            );
+
+            assert_eq!(stats.unhandled_csi, 3);
+            assert_eq!(stats.unhandled_esc, 1);
+            assert_eq!(stats.unhandled_osc, 1);
+            assert_eq!(stats.unhandled_dcs, 1);
+            assert_eq!(stats.unhandled_execute, 1);
+            assert_eq!(stats.unhandled(), 7);
+        }
+
+        /// Deterministic xorshift generator, so failures can be reproduced.
+        struct Rng(u64);
+
+        impl Rng {
+            fn next(&mut self) -> u64 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0
+            }
+
+            fn below(&mut self, n: u64) -> u64 {
+                self.next() % n
+            }
+        }
+
+        fn random_bytes(rng: &mut Rng, len: usize) -> Vec<u8> {
+            const INTERESTING: &[u8] = b"\x1b\x1b\x1b[[]PX^_\\;:?>!$#%()*+ 0123456789\x07\x08\x09\x0a\x0d\x0e\x0f\x18\x1a\x84\x85\x88\x8d\x90\x9b\x9c\x9d\x7f";
+
+            (0..len)
+                .map(|_| match rng.below(4) {
+                    0 => rng.next() as u8,
+                    1 => b'@' + rng.below(63) as u8,
+                    _ => INTERESTING[rng.below(INTERESTING.len() as u64) as usize],
+                })
+                .collect()
+        }
+
+        #[test]
+        fn arbitrary_input_never_panics() {
+            const SIZES: &[(u16, u16)] = &[(1, 1), (1, 2), (2, 1), (3, 7), (24, 80)];
+
+            let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+            for _ in 0..500 {
+                for &(rows, cols) in SIZES {
+                    let mut state = State::with_dimensions(Row(rows), Col(cols));
+                    let bytes = random_bytes(&mut rng, 256);
+                    feed(&mut state, &bytes);
+
+                    let (rows, cols) = SIZES[rng.below(SIZES.len() as u64) as usize];
+                    state.resize(Row(rows), Col(cols));
+
+                    let bytes = random_bytes(&mut rng, 64);
+                    feed(&mut state, &bytes);
+                }
+            }
+        }
+
+        #[test]
+        fn huge_parameters_never_panic() {
+            let mut state = State::with_dimensions(Row(3), Col(4));
+
+            for action in b"@ABCDEFGHIJKLMPSTXZ`abdefghlmnrsu".iter() {
+                let mut bytes = b"\x1b[65535;65535".to_vec();
+                bytes.push(*action);
+                feed(&mut state, &bytes);
+
+                let mut bytes = b"\x1b[?65535;65535".to_vec();
+                bytes.push(*action);
+                feed(&mut state, &bytes);
+            }
+        }
+    }
+
+    mod paste {
+        use super::super::paste_bytes;
+
+        #[test]
+        fn newlines_become_returns() {
+            assert_eq!(paste_bytes("a\nb\r\nc", false), b"a\rb\rc");
+        }
+
+        #[test]
+        fn bracketed() {
+            assert_eq!(paste_bytes("ab\n", true), b"\x1b[200~ab\r\x1b[201~");
+
+            let sneaky = "a\x1b[201\x1b[201~~b";
+            assert_eq!(paste_bytes(sneaky, true), b"\x1b[200~ab\x1b[201~");
+        }
     }
 }
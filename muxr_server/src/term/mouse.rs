@@ -0,0 +1,213 @@
+use muxr_core::input::{Modifiers, MouseButton, MouseEvent, MouseKind};
+use muxr_core::state::{Col, MouseEncoding, MouseTracking, Row, State};
+
+use std::cmp;
+
+/// The bytes reporting `event` to the child, or `None` if it hasn't asked for
+/// events like it. Events the encoding can't represent, like clicks past
+/// column 223 in the default encoding, aren't reported either.
+///
+/// The client's terminal is all one pane, so positions only need clamping to
+/// the screen.
+pub fn encode(event: &MouseEvent, state: &State) -> Option<Vec<u8>> {
+    let tracking = state.mouse_tracking();
+    let wheel = event.button.is_some_and(MouseButton::is_wheel);
+
+    let wanted = match (tracking, event.kind) {
+        (MouseTracking::Off, _) => false,
+        (_, MouseKind::Press) => true,
+        (MouseTracking::X10, _) => false,
+        // Wheels don't have releases to report.
+        (_, MouseKind::Release) => !wheel,
+        (MouseTracking::Normal, _) => false,
+        (MouseTracking::Button, MouseKind::Drag) => true,
+        (MouseTracking::Button, MouseKind::Move) => false,
+        (MouseTracking::Any, _) => true,
+    };
+
+    if !wanted {
+        return None;
+    }
+
+    let (row, col) = event.position;
+
+    // Presses outside the screen don't belong to the child, but drags off
+    // the edge are reported at the edge.
+    if event.kind == MouseKind::Press && (row >= state.rows() || col >= state.columns()) {
+        return None;
+    }
+
+    let row = cmp::min(row, state.rows() - Row(1)).0 as u32;
+    let col = cmp::min(col, state.columns() - Col(1)).0 as u32;
+
+    let encoding = state.mouse_encoding();
+
+    let mut button = match event.button {
+        Some(MouseButton::Left) => 0,
+        Some(MouseButton::Middle) => 1,
+        Some(MouseButton::Right) => 2,
+        Some(MouseButton::WheelUp) => 64,
+        Some(MouseButton::WheelDown) => 65,
+        Some(MouseButton::WheelLeft) => 66,
+        Some(MouseButton::WheelRight) => 67,
+        None => 3,
+    };
+
+    // Only SGR can say which button was released.
+    if event.kind == MouseKind::Release && encoding != MouseEncoding::Sgr {
+        button = 3;
+    }
+
+    if let MouseKind::Drag | MouseKind::Move = event.kind {
+        button += 32;
+    }
+
+    // X10 mode doesn't report modifiers.
+    if tracking != MouseTracking::X10 {
+        if event.modifiers.contains(Modifiers::SHIFT) {
+            button += 4;
+        }
+        if event.modifiers.contains(Modifiers::ALT) {
+            button += 8;
+        }
+        if event.modifiers.contains(Modifiers::CTRL) {
+            button += 16;
+        }
+    }
+
+    let bytes = match encoding {
+        MouseEncoding::Sgr => {
+            let last = if event.kind == MouseKind::Release {
+                'm'
+            } else {
+                'M'
+            };
+
+            format!("\x1b[<{};{};{}{}", button, col + 1, row + 1, last).into_bytes()
+        }
+        MouseEncoding::Urxvt => {
+            format!("\x1b[{};{};{}M", button + 32, col + 1, row + 1).into_bytes()
+        }
+        MouseEncoding::X10 => {
+            if col + 33 > 0xff || row + 33 > 0xff {
+                return None;
+            }
+
+            vec![
+                0x1b,
+                b'[',
+                b'M',
+                button + 32,
+                col as u8 + 33,
+                row as u8 + 33,
+            ]
+        }
+        MouseEncoding::Utf8 => {
+            let mut bytes = b"\x1b[M".to_vec();
+
+            for value in &[u32::from(button) + 32, col + 33, row + 33] {
+                // Two byte UTF-8 sequences are as far as xterm goes.
+                match std::char::from_u32(*value).filter(|c| c.len_utf8() <= 2) {
+                    Some(c) => bytes.extend(c.to_string().as_bytes()),
+                    None => return None,
+                }
+            }
+
+            bytes
+        }
+    };
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: MouseKind, button: Option<MouseButton>, row: u16, col: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            button,
+            modifiers: Modifiers::empty(),
+            position: (Row(row), Col(col)),
+        }
+    }
+
+    fn state(tracking: MouseTracking, encoding: MouseEncoding) -> State {
+        let mut state = State::with_dimensions(Row(24), Col(300));
+        state.set_mouse_tracking(tracking, true);
+        state.set_mouse_encoding(encoding, true);
+        state
+    }
+
+    const LEFT: Option<MouseButton> = Some(MouseButton::Left);
+
+    #[test]
+    fn off_reports_nothing() {
+        let state = State::default();
+        let press = event(MouseKind::Press, LEFT, 0, 0);
+
+        assert_eq!(encode(&press, &state), None);
+    }
+
+    #[test]
+    fn tracking_modes() {
+        let press = event(MouseKind::Press, LEFT, 1, 2);
+        let release = event(MouseKind::Release, LEFT, 1, 2);
+        let drag = event(MouseKind::Drag, LEFT, 1, 3);
+        let moved = event(MouseKind::Move, None, 1, 3);
+
+        let x10 = state(MouseTracking::X10, MouseEncoding::X10);
+        assert_eq!(encode(&press, &x10).unwrap(), b"\x1b[M #\"");
+        assert_eq!(encode(&release, &x10), None);
+
+        let normal = state(MouseTracking::Normal, MouseEncoding::X10);
+        assert_eq!(encode(&release, &normal).unwrap(), b"\x1b[M##\"");
+        assert_eq!(encode(&drag, &normal), None);
+
+        let button = state(MouseTracking::Button, MouseEncoding::X10);
+        assert_eq!(encode(&drag, &button).unwrap(), b"\x1b[M@$\"");
+        assert_eq!(encode(&moved, &button), None);
+
+        let any = state(MouseTracking::Any, MouseEncoding::X10);
+        assert_eq!(encode(&moved, &any).unwrap(), b"\x1b[MC$\"");
+    }
+
+    #[test]
+    fn sgr_and_urxvt() {
+        let mut press = event(MouseKind::Press, Some(MouseButton::Right), 9, 250);
+        press.modifiers = Modifiers::CTRL | Modifiers::SHIFT;
+        let release = event(MouseKind::Release, Some(MouseButton::Right), 9, 250);
+        let wheel = event(MouseKind::Press, Some(MouseButton::WheelDown), 0, 0);
+
+        let sgr = state(MouseTracking::Normal, MouseEncoding::Sgr);
+        assert_eq!(encode(&press, &sgr).unwrap(), b"\x1b[<22;251;10M");
+        assert_eq!(encode(&release, &sgr).unwrap(), b"\x1b[<2;251;10m");
+        assert_eq!(encode(&wheel, &sgr).unwrap(), b"\x1b[<65;1;1M");
+
+        let urxvt = state(MouseTracking::Normal, MouseEncoding::Urxvt);
+        assert_eq!(encode(&release, &urxvt).unwrap(), b"\x1b[35;251;10M");
+    }
+
+    #[test]
+    fn large_coordinates() {
+        let press = event(MouseKind::Press, LEFT, 0, 250);
+
+        let x10 = state(MouseTracking::Normal, MouseEncoding::X10);
+        assert_eq!(encode(&press, &x10), None);
+
+        let utf8 = state(MouseTracking::Normal, MouseEncoding::Utf8);
+        assert_eq!(encode(&press, &utf8).unwrap(), "\x1b[M \u{11b}!".as_bytes());
+    }
+
+    #[test]
+    fn positions_outside_the_screen() {
+        let sgr = state(MouseTracking::Button, MouseEncoding::Sgr);
+
+        let press = event(MouseKind::Press, LEFT, 30, 0);
+        assert_eq!(encode(&press, &sgr), None);
+
+        let drag = event(MouseKind::Drag, LEFT, 30, 400);
+        assert_eq!(encode(&drag, &sgr).unwrap(), b"\x1b[<32;300;24M");
+    }
+}